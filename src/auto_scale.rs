@@ -0,0 +1,147 @@
+// Automatic DSP scale selection
+// Captures channelized IQ at every valid FFT scale and picks the one with the most headroom used without overflow
+// Called to in gui
+
+use crate::gui::VALID_FFT_SCALES;
+use crate::saturation::{self, SaturationReport, Stage};
+use crate::worker::{RPCCommand, ScaleCapture};
+use eframe::egui;
+
+// Channels of the loaded comb captured at each scale
+const SCAN_CHANNELS: usize = 8;
+
+// Outcome at one scale
+pub struct ScaleResult {
+    pub requested: u16,
+    pub applied: Option<u16>,
+    pub report: Option<SaturationReport>, // None if setting the scale or capturing failed
+}
+
+pub struct AutoScale {
+    pub length: String, // Samples per capture
+    pub running: bool,
+    pub results: Vec<ScaleResult>,
+    pub best: Option<u16>,
+    pub message: Option<String>,
+}
+
+impl Default for AutoScale {
+    fn default() -> Self {
+        Self {
+            length: "16384".to_string(),
+            running: false,
+            results: Vec::new(),
+            best: None,
+            message: None,
+        }
+    }
+}
+
+impl AutoScale {
+    // Scan command for the worker, capturing the first channels of a comb with this many tones
+    fn start(&mut self, tones: usize) -> Result<RPCCommand, String> {
+        let length = self.length.trim().parse::<u64>().ok().filter(|l| *l > 0).ok_or_else(|| "Invalid capture length.".to_string())?;
+        if tones == 0 {
+            return Err("Generate or load a DAC comb first; the scan captures its channels.".to_string());
+        }
+        self.running = true;
+        self.results.clear();
+        self.best = None;
+        Ok(RPCCommand::FFTScaleScan(VALID_FFT_SCALES.to_vec(), (0..tones.min(SCAN_CHANNELS)).collect(), length))
+    }
+
+    // Analyse the scan; returns the scale to apply. The largest peak without clipping has the most dynamic range
+    // above the quantisation floor; of equal peaks the scale with more shifts leaves more margin inside the FFT.
+    pub fn receive(&mut self, captures: Option<Vec<ScaleCapture>>) -> Option<u16> {
+        self.running = false;
+        let Some(captures) = captures else {
+            self.message = Some("Scale scan failed: could not read the current DSP scale.".to_string());
+            return None;
+        };
+
+        self.results = captures
+            .into_iter()
+            .map(|c| ScaleResult {
+                requested: c.requested,
+                applied: c.applied,
+                report: (c.applied.is_some() && !c.data.is_empty()).then(|| saturation::analyse(Stage::Dsp, &c.data)),
+            })
+            .collect();
+
+        let best = self
+            .results
+            .iter()
+            .filter_map(|r| Some((r.applied?, r.report.as_ref()?)))
+            .filter(|(_, report)| report.clipped == 0)
+            .max_by_key(|(scale, report)| (report.peak, scale.count_ones()));
+        match best {
+            Some((scale, report)) => {
+                self.best = Some(scale);
+                self.message = Some(format!("Applying DSP scale {} (peak {:.1} dBFS).", scale, report.peak_dbfs));
+                Some(scale)
+            }
+            None => {
+                self.message = Some("Every scale overflowed or failed; DSP scale left unchanged. Raise the attenuation.".to_string());
+                None
+            }
+        }
+    }
+
+    // Draw the auto-scale group; tones is the size of the loaded comb. Returns a command for the worker when a scan is started
+    pub fn show(&mut self, ui: &mut egui::Ui, tones: usize) -> Option<RPCCommand> {
+        let mut command = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Samples per Capture:");
+            ui.text_edit_singleline(&mut self.length);
+            if self.running {
+                ui.label("Scanning...");
+            } else if ui.button("Auto Scale").clicked() {
+                match self.start(tones) {
+                    Ok(c) => {
+                        command = Some(c);
+                        self.message = None;
+                    }
+                    Err(e) => self.message = Some(e),
+                }
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        if self.results.is_empty() {
+            return command;
+        }
+        egui::Grid::new("auto_scale_results").striped(true).show(ui, |ui| {
+            ui.label("Scale");
+            ui.label("Applied");
+            ui.label("Peak (dBFS)");
+            ui.label("Clipped");
+            ui.end_row();
+            for r in &self.results {
+                let text = format!("{}", r.requested);
+                if r.applied.is_some() && r.applied == self.best {
+                    ui.strong(text);
+                } else {
+                    ui.label(text);
+                }
+                ui.label(r.applied.map_or("failed".to_string(), |a| a.to_string()));
+                match r.report {
+                    Some(ref report) => {
+                        ui.label(format!("{:.1}", report.peak_dbfs));
+                        ui.label(format!("{:.3}%", 100.0 * report.fraction()));
+                    }
+                    None => {
+                        ui.label("-");
+                        ui.label("capture failed");
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        command
+    }
+}
@@ -0,0 +1,115 @@
+// Board state consistency
+// Keeps track of the worker's periodic board state reads and of settings changed behind the GUI's back
+// Called to in gui
+
+use crate::worker::{BoardState, RPCCommand, TABLE_POLL_EVERY};
+use eframe::egui;
+use std::time::{Duration, SystemTime};
+
+pub struct BoardStateMonitor {
+    pub poll: bool,       // Worker re-reads the board periodically
+    pub interval: String, // Poll interval (s)
+    pub last_read: Option<SystemTime>,
+    pub divergences: Vec<(SystemTime, String)>, // Oldest first
+    pub unacknowledged: usize,                  // Divergences not yet acknowledged by the user
+    pub message: Option<String>,
+}
+
+impl Default for BoardStateMonitor {
+    fn default() -> Self {
+        Self {
+            poll: true,
+            interval: "10".to_string(), // Matches the worker's default
+            last_read: None,
+            divergences: Vec::new(),
+            unacknowledged: 0,
+            message: None,
+        }
+    }
+}
+
+impl BoardStateMonitor {
+    // Log a read from the worker
+    pub fn receive(&mut self, state: &BoardState) {
+        self.last_read = Some(state.time);
+        self.unacknowledged += state.divergences.len();
+        self.divergences.extend(state.divergences.iter().map(|d| (state.time, d.clone())));
+    }
+
+    // Status bar warning while there are unacknowledged divergences
+    pub fn warning(&self) -> Option<String> {
+        let (_, latest) = self.divergences.last().filter(|_| self.unacknowledged > 0)?;
+        Some(format!("Board state changed outside this GUI: {} ({} unacknowledged)", latest, self.unacknowledged))
+    }
+
+    // Poll command for the worker
+    fn poll_command(&self) -> Result<RPCCommand, String> {
+        if !self.poll {
+            return Ok(RPCCommand::SetStatePoll(None));
+        }
+        match self.interval.trim().parse::<f64>() {
+            Ok(s) if s > 0.0 => Ok(RPCCommand::SetStatePoll(Some(Duration::from_secs_f64(s)))),
+            _ => Err("Invalid poll interval.".to_string()),
+        }
+    }
+
+    // Draw the board state group; returns a command for the worker
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<RPCCommand> {
+        let mut command = None;
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.poll, "Poll every");
+            ui.add(egui::TextEdit::singleline(&mut self.interval).desired_width(60.0));
+            ui.label("s");
+            if ui.button("Apply").clicked() {
+                match self.poll_command() {
+                    Ok(c) => {
+                        command = Some(c);
+                        self.message = None;
+                    }
+                    Err(e) => self.message = Some(e),
+                }
+            }
+            if ui.button("Read Now").clicked() {
+                command = Some(RPCCommand::ReadBoardState);
+            }
+        });
+
+        ui.label(format!(
+            "Polls read the DSP scale, IF frequency and attenuation, and the DAC table every {} polls; Read Now reads everything.",
+            TABLE_POLL_EVERY
+        ));
+        match self.last_read.and_then(|t| t.elapsed().ok()) {
+            Some(age) => ui.label(format!("Last read {:.0?} ago", Duration::from_secs(age.as_secs()))),
+            None => ui.label("Board state not read yet."),
+        };
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} divergences", self.divergences.len()));
+            if ui.button("Acknowledge").clicked() {
+                self.unacknowledged = 0;
+            }
+            if ui.button("Clear").clicked() {
+                self.divergences.clear();
+                self.unacknowledged = 0;
+            }
+        });
+        egui::ScrollArea::vertical().id_salt("divergences").max_height(150.0).show(ui, |ui| {
+            let new = self.divergences.len() - self.unacknowledged.min(self.divergences.len());
+            for (i, (time, divergence)) in self.divergences.iter().enumerate().rev() {
+                let age = time.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+                let text = format!("{} s ago: {}", age, divergence);
+                if i >= new {
+                    ui.colored_label(egui::Color32::RED, text);
+                } else {
+                    ui.label(text);
+                }
+            }
+        });
+
+        command
+    }
+}
@@ -0,0 +1,114 @@
+// DAC tone comb generation
+// Builds the 2^19 sample DAC table for a list of tones relative to the IF LO frequency
+// Called to in gui
+
+use num::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Number of samples in the DAC table
+pub const DAC_TABLE_LEN: usize = 524288;
+
+// DAC sample rate (Hz)
+pub const DAC_SAMPLE_RATE: f64 = 4.096e9;
+
+// Frequency resolution of the DAC table (Hz)
+pub const DAC_BIN_WIDTH: f64 = DAC_SAMPLE_RATE / DAC_TABLE_LEN as f64;
+
+// A full DAC table, shared between the GUI and the worker instead of copied
+pub type DacTable = Arc<[Complex<i16>; DAC_TABLE_LEN]>;
+
+// A single tone of the comb
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tone {
+    pub freq: f64,      // RF frequency (Hz)
+    pub amplitude: f64, // Relative amplitude (the loudest tone is 1)
+    pub phase: f64,     // Extra phase offset (rad)
+    #[serde(default)]
+    pub id: Option<u32>, // Resonator the tone was generated for
+}
+
+// A generated comb and the tones and settings it was built from
+#[derive(Clone)]
+pub struct DacComb {
+    pub tones: Vec<Tone>,
+    pub lo: f64,   // IF LO frequency (Hz)
+    pub fill: f64, // Fraction of full scale
+    pub table: DacTable,
+}
+
+impl DacComb {
+    // Resonator ID read out by each channel; channel i is the i-th tone of the comb
+    pub fn channel_resonators(&self) -> Vec<Option<u32>> {
+        self.tones.iter().map(|t| t.id).collect()
+    }
+}
+
+// Fingerprint of a DAC table, for spotting changed tables without keeping a copy.
+// 64-bit FNV-1a over the little-endian samples, stable across builds so it can be stored with saved tables.
+pub fn table_hash(table: &[Complex<i16>]) -> u64 {
+    table
+        .iter()
+        .flat_map(|z| z.re.to_le_bytes().into_iter().chain(z.im.to_le_bytes()))
+        .fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// Owned copy of a table in the form the board client uploads, built on the heap
+pub fn to_board(table: &DacTable) -> Box<[Complex<i16>; DAC_TABLE_LEN]> {
+    table.to_vec().into_boxed_slice().try_into().unwrap()
+}
+
+// DAC table bin index of an RF frequency, or None if it is outside the DAC band around the LO
+pub fn tone_bin(freq: f64, lo: f64) -> Option<usize> {
+    let baseband = freq - lo;
+    if baseband.abs() >= DAC_SAMPLE_RATE / 2.0 {
+        return None;
+    }
+    let k = (baseband / DAC_BIN_WIDTH).round() as i64;
+    Some(k.rem_euclid(DAC_TABLE_LEN as i64) as usize)
+}
+
+// Generate the DAC table for a list of tones. Newman phases keep the crest factor low and the
+// output is scaled so the peak sample uses `fill` of the i16 range.
+pub fn generate(tones: &[Tone], lo: f64, fill: f64) -> Result<DacComb, String> {
+    if tones.is_empty() {
+        return Err("No tones to generate.".to_string());
+    }
+
+    let mut spectrum = vec![Complex::new(0.0f64, 0.0); DAC_TABLE_LEN];
+    let mut used = vec![false; DAC_TABLE_LEN]; // Zero-amplitude tones still occupy their bin
+    let m = tones.len() as f64;
+    for (i, tone) in tones.iter().enumerate() {
+        let bin = tone_bin(tone.freq, lo)
+            .ok_or_else(|| format!("Tone at {} Hz is outside the DAC band around the LO ({} Hz).", tone.freq, lo))?;
+        if std::mem::replace(&mut used[bin], true) {
+            return Err(format!("Tone at {} Hz shares a DAC bin with another tone.", tone.freq));
+        }
+        let newman = PI * (i as f64).powi(2) / m;
+        spectrum[bin] = Complex::from_polar(tone.amplitude, newman + tone.phase);
+    }
+
+    // Inverse FFT gives the time domain waveform
+    FftPlanner::new().plan_fft_inverse(DAC_TABLE_LEN).process(&mut spectrum);
+
+    let peak = spectrum.iter().map(|z| z.re.abs().max(z.im.abs())).fold(0.0, f64::max);
+    if peak == 0.0 {
+        return Err("All tones have zero amplitude.".to_string());
+    }
+    let scale = fill.clamp(0.0, 1.0) * i16::MAX as f64 / peak;
+
+    let samples: Vec<Complex<i16>> = spectrum
+        .iter()
+        .map(|z| Complex::new((z.re * scale).round() as i16, (z.im * scale).round() as i16))
+        .collect();
+    let table: Box<[Complex<i16>; DAC_TABLE_LEN]> = samples.into_boxed_slice().try_into().unwrap();
+
+    Ok(DacComb {
+        tones: tones.to_vec(),
+        lo,
+        fill,
+        table: table.into(),
+    })
+}
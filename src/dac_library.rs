@@ -0,0 +1,416 @@
+// DAC table library
+// Keeps named DAC tables on disk (npy + JSON metadata) to browse, preview, diff and apply, and notes which one is on the board
+// Called to in gui
+
+use crate::dac_comb::{table_hash, DacComb, DacTable, Tone, DAC_BIN_WIDTH, DAC_TABLE_LEN};
+use crate::live_capture::{spectrum_db, spectrum_freqs};
+use crate::verify;
+use eframe::egui;
+use egui_plot::{Line, Plot};
+use num::Complex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Spectrum preview is max-pooled down to this many points
+const PREVIEW_POINTS: usize = 4096;
+
+// Description saved next to each table
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TableMetadata {
+    pub name: String,
+    pub created: u64,     // Unix time (s)
+    pub hash: String,     // table_hash as hex
+    pub tones: Vec<Tone>, // Empty for tables saved from a board read-back
+    pub lo: Option<f64>,  // IF LO frequency the comb was generated for (Hz)
+    pub fill: Option<f64>, // Fraction of full scale used by the generator
+    pub notes: String,
+}
+
+// Library table currently on the board, written to loaded.json in the library
+#[derive(Serialize)]
+struct LoadedRecord<'a> {
+    name: &'a str,
+    hash: &'a str,
+    time: u64, // Unix time (s) it was seen on the board
+}
+
+// Table read from the library, with its tones and generator settings if it was generated
+pub struct LibraryTable {
+    pub tones: Vec<Tone>,
+    pub lo: Option<f64>,
+    pub fill: Option<f64>,
+    pub table: DacTable,
+}
+
+impl LibraryTable {
+    // The comb it was generated as, so it can be retuned after applying
+    pub fn comb(&self) -> Option<DacComb> {
+        match (self.tones.is_empty(), self.lo, self.fill) {
+            (false, Some(lo), Some(fill)) => Some(DacComb {
+                tones: self.tones.clone(),
+                lo,
+                fill,
+                table: self.table.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Requests for the GUI
+pub enum LibraryAction {
+    Save,
+    Apply(LibraryTable),
+}
+
+pub struct DacLibrary {
+    pub directory: String,
+    pub name: String,  // Name for the next save
+    pub notes: String, // Notes for the next save
+    pub entries: Vec<TableMetadata>, // Newest first
+    pub selected: Option<usize>,
+    pub compare: Option<usize>,
+    pub loaded: Option<String>, // Library name of the table on the board
+    board_hash: Option<u64>,
+    recorded: Option<u64>, // Board table hash loaded.json was last written for
+    preview: Option<(String, Vec<[f64; 2]>)>, // Name and spectrum (MHz, dB)
+    diff: Vec<String>,
+    scanned: bool, // Library folder read at least once
+    pub message: Option<String>,
+}
+
+impl Default for DacLibrary {
+    fn default() -> Self {
+        Self {
+            directory: "dac_tables".to_string(),
+            name: String::new(),
+            notes: String::new(),
+            entries: Vec::new(),
+            selected: None,
+            compare: None,
+            loaded: None,
+            board_hash: None,
+            recorded: None,
+            preview: None,
+            diff: Vec::new(),
+            scanned: false,
+            message: None,
+        }
+    }
+}
+
+fn hash_hex(table: &[Complex<i16>]) -> String {
+    format!("{:016x}", table_hash(table))
+}
+
+// Tone changes from a to b, matching tones that fall in the same DAC bin
+fn tone_diff(a: &[Tone], b: &[Tone]) -> Vec<String> {
+    let same_bin = |x: &Tone, y: &Tone| (x.freq - y.freq).abs() < DAC_BIN_WIDTH / 2.0;
+    let mut lines = Vec::new();
+    for t in a {
+        match b.iter().find(|u| same_bin(t, u)) {
+            None => lines.push(format!("- tone at {:.6} MHz", t.freq / 1e6)),
+            Some(u) if u != t => lines.push(format!(
+                "~ tone at {:.6} MHz: amplitude {:.3} -> {:.3}, phase {:.3} -> {:.3} rad",
+                t.freq / 1e6,
+                t.amplitude,
+                u.amplitude,
+                t.phase,
+                u.phase
+            )),
+            Some(_) => {}
+        }
+    }
+    for u in b.iter().filter(|u| !a.iter().any(|t| same_bin(t, u))) {
+        lines.push(format!("+ tone at {:.6} MHz", u.freq / 1e6));
+    }
+    lines
+}
+
+impl DacLibrary {
+    fn table_path(&self, name: &str) -> PathBuf {
+        Path::new(self.directory.trim()).join(format!("{}.npy", name))
+    }
+
+    fn meta_path(&self, name: &str) -> PathBuf {
+        Path::new(self.directory.trim()).join(format!("{}.json", name))
+    }
+
+    // Re-read the metadata of every table in the library directory
+    pub fn refresh(&mut self) -> Result<(), String> {
+        self.scanned = true;
+        self.entries.clear();
+        self.selected = None;
+        self.compare = None;
+        self.diff.clear();
+        self.preview = None;
+        self.recorded = None;
+        let directory = Path::new(self.directory.trim());
+        let read = std::fs::read_dir(directory).map_err(|e| format!("Failed to read {}: {}", directory.display(), e))?;
+        self.entries = read
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|x| x == "json") && p.file_stem().is_some_and(|s| s != "loaded"))
+            .filter_map(|p| serde_json::from_str::<TableMetadata>(&std::fs::read_to_string(p).ok()?).ok())
+            .collect();
+        self.entries.sort_by_key(|e| std::cmp::Reverse(e.created));
+        self.identify();
+        Ok(())
+    }
+
+    // Save a table under the entered name
+    pub fn save(&mut self, table: &DacTable, tones: Vec<Tone>, lo: Option<f64>, fill: Option<f64>) -> Result<String, String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') || name == "loaded" {
+            return Err("Enter a name of letters, digits, '-' and '_'.".to_string());
+        }
+        // Check the folder itself, the list may be older than files written by others
+        if self.table_path(&name).exists() || self.meta_path(&name).exists() {
+            return Err(format!("A table named {} already exists in {}.", name, self.directory.trim()));
+        }
+        std::fs::create_dir_all(self.directory.trim()).map_err(|e| format!("Failed to create {}: {}", self.directory.trim(), e))?;
+
+        let metadata = TableMetadata {
+            name: name.clone(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            hash: hash_hex(&table[..]),
+            tones,
+            lo,
+            fill,
+            notes: self.notes.clone(),
+        };
+        let (table_path, meta_path) = (self.table_path(&name), self.meta_path(&name));
+        let array = ndarray::Array2::from_shape_fn((DAC_TABLE_LEN, 2), |(i, j)| if j == 0 { table[i].re } else { table[i].im });
+        ndarray_npy::write_npy(&table_path, &array).map_err(|e| format!("Failed to write {}: {}", table_path.display(), e))?;
+        serde_json::to_string_pretty(&metadata)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&meta_path, json))
+            .map_err(|e| format!("Failed to write {}: {}", meta_path.display(), e))?;
+
+        self.entries.insert(0, metadata);
+        self.selected = Some(0);
+        self.compare = None;
+        self.recorded = None; // The board's table may be the one just saved
+        self.identify();
+        Ok(format!("Saved {} to {}", name, table_path.display()))
+    }
+
+    // Read a library table and check it against its recorded hash
+    fn load(&self, index: usize) -> Result<LibraryTable, String> {
+        let entry = &self.entries[index];
+        let path = self.table_path(&entry.name);
+        let array: ndarray::Array2<i16> = ndarray_npy::read_npy(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if array.dim() != (DAC_TABLE_LEN, 2) {
+            return Err(format!("{} has shape {:?}, expected ({}, 2).", path.display(), array.dim(), DAC_TABLE_LEN));
+        }
+        let samples: Vec<Complex<i16>> = array.rows().into_iter().map(|r| Complex::new(r[0], r[1])).collect();
+        if hash_hex(&samples) != entry.hash {
+            return Err(format!("{} does not match its recorded hash.", path.display()));
+        }
+        let table: Box<[Complex<i16>; DAC_TABLE_LEN]> = samples.into_boxed_slice().try_into().unwrap();
+        Ok(LibraryTable {
+            tones: entry.tones.clone(),
+            lo: entry.lo,
+            fill: entry.fill,
+            table: table.into(),
+        })
+    }
+
+    // Note the table the board holds, from a DAC table read or upload
+    pub fn board_table(&mut self, table: Option<&DacTable>) {
+        self.board_hash = table.map(|t| table_hash(&t[..]));
+        self.identify();
+    }
+
+    // The comb a generated library table with this hash was made from, for a table applied from elsewhere
+    pub fn comb_for(&self, table: &DacTable, hash: u64) -> Option<DacComb> {
+        let hash = format!("{:016x}", hash);
+        let entry = self.entries.iter().find(|e| e.hash == hash)?;
+        LibraryTable { tones: entry.tones.clone(), lo: entry.lo, fill: entry.fill, table: table.clone() }.comb()
+    }
+
+    // Match the board's table against the library and record it. A table not in the library removes the
+    // record so it never names a table that has since been replaced.
+    fn identify(&mut self) {
+        let hash = self.board_hash.map(|h| format!("{:016x}", h));
+        self.loaded = hash.as_ref().and_then(|h| self.entries.iter().find(|e| e.hash == *h)).map(|e| e.name.clone());
+        let (Some(board), Some(hash)) = (self.board_hash, hash) else {
+            return; // Board table unknown, leave the record alone
+        };
+        if self.recorded == Some(board) {
+            return;
+        }
+        self.recorded = Some(board);
+        let path = Path::new(self.directory.trim()).join("loaded.json");
+        let result = match self.loaded {
+            Some(ref name) => {
+                let record = LoadedRecord {
+                    name,
+                    hash: &hash,
+                    time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                };
+                serde_json::to_string_pretty(&record).map_err(std::io::Error::from).and_then(|json| std::fs::write(&path, json))
+            }
+            None => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                r => r,
+            },
+        };
+        if let Err(e) = result {
+            self.message = Some(format!("Failed to update {}: {}", path.display(), e));
+        }
+    }
+
+    // Spectrum of the selected table
+    fn build_preview(&mut self, index: usize) -> Result<(), String> {
+        let table = self.load(index)?;
+        let spectrum = spectrum_db(&table.table[..]);
+        let freqs = spectrum_freqs(spectrum.len());
+        let pool = spectrum.len().div_ceil(PREVIEW_POINTS);
+        let points = spectrum
+            .chunks(pool)
+            .zip(freqs.chunks(pool))
+            .map(|(p, f)| [f[0], p.iter().copied().fold(f64::NEG_INFINITY, f64::max)])
+            .collect();
+        self.preview = Some((self.entries[index].name.clone(), points));
+        Ok(())
+    }
+
+    // Differences between the selected table and the compared table, or the board's table
+    fn build_diff(&mut self, index: usize, board: Option<&DacTable>) -> Result<(), String> {
+        let a = self.load(index)?;
+        let (other, b_tones, b) = match self.compare {
+            Some(c) => {
+                let b = self.load(c)?;
+                (self.entries[c].name.clone(), Some(b.tones), b.table)
+            }
+            None => ("board".to_string(), None, board.cloned().ok_or_else(|| "Board DAC table unknown; get the DAC table first.".to_string())?),
+        };
+
+        let mut lines = vec![format!("{} -> {}", self.entries[index].name, other)];
+        match verify::table_diff(&a.table[..], &b[..]) {
+            Some(d) => lines.push(format!("Samples: {}", d)),
+            None => lines.push("Samples: identical".to_string()),
+        }
+        if let Some(b_tones) = b_tones {
+            let tones = tone_diff(&a.tones, &b_tones);
+            lines.push(format!("Tones: {} -> {}, {} changes", a.tones.len(), b_tones.len(), tones.len()));
+            lines.extend(tones);
+        }
+        self.diff = lines;
+        Ok(())
+    }
+
+    // Draw the library; board is the table last read from the board
+    pub fn show(&mut self, ui: &mut egui::Ui, board: Option<&DacTable>) -> Option<LibraryAction> {
+        let mut action = None;
+
+        if !self.scanned {
+            // A missing folder just means nothing has been saved yet
+            let _ = self.refresh();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Library Folder:");
+            if ui.text_edit_singleline(&mut self.directory).changed() {
+                // Never list one folder while loading from and saving to another
+                let _ = self.refresh();
+            }
+            if ui.button("Refresh").clicked() {
+                self.message = self.refresh().err();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.name);
+            ui.label("Notes:");
+            ui.text_edit_singleline(&mut self.notes);
+            if ui.button("Save Current Table").clicked() {
+                action = Some(LibraryAction::Save);
+            }
+        });
+        ui.label(match self.loaded {
+            Some(ref name) => format!("On the board: {}", name),
+            None if self.board_hash.is_some() => "On the board: a table not in the library".to_string(),
+            None => "On the board: unknown (get the DAC table)".to_string(),
+        });
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        let mut preview = None;
+        egui::ScrollArea::vertical().id_salt("dac_library").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("dac_library_grid").striped(true).show(ui, |ui| {
+                ui.label("Name");
+                ui.label("Created");
+                ui.label("Tones");
+                ui.label("Hash");
+                ui.label("Compare");
+                ui.label("Notes");
+                ui.end_row();
+                for (i, entry) in self.entries.iter().enumerate() {
+                    let on_board = self.loaded.as_ref() == Some(&entry.name);
+                    let label = if on_board { format!("{} (loaded)", entry.name) } else { entry.name.clone() };
+                    if ui.selectable_label(self.selected == Some(i), label).clicked() {
+                        self.selected = Some(i);
+                        preview = Some(i);
+                    }
+                    let age = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0).saturating_sub(entry.created);
+                    ui.label(format!("{:.1} h ago", age as f64 / 3600.0));
+                    ui.label(entry.tones.len().to_string());
+                    ui.label(&entry.hash[..8.min(entry.hash.len())]);
+                    if ui.selectable_label(self.compare == Some(i), "B").clicked() {
+                        self.compare = if self.compare == Some(i) { None } else { Some(i) };
+                    }
+                    ui.label(&entry.notes);
+                    ui.end_row();
+                }
+            });
+        });
+        if let Some(i) = preview {
+            self.message = self.build_preview(i).err();
+        }
+
+        let Some(selected) = self.selected.filter(|s| *s < self.entries.len()) else {
+            return action;
+        };
+        ui.horizontal(|ui| {
+            let target = self.compare.map_or("Board".to_string(), |c| self.entries[c].name.clone());
+            if ui.button(format!("Diff Against {}", target)).clicked() {
+                self.message = self.build_diff(selected, board).err();
+            }
+            if ui.button("Apply To Board").clicked() {
+                match self.load(selected) {
+                    Ok(table) => action = Some(LibraryAction::Apply(table)),
+                    Err(e) => self.message = Some(e),
+                }
+            }
+        });
+
+        if !self.diff.is_empty() {
+            egui::ScrollArea::vertical().id_salt("dac_library_diff").max_height(150.0).show(ui, |ui| {
+                for line in &self.diff {
+                    ui.label(line);
+                }
+            });
+        }
+
+        if let Some((ref name, ref points)) = self.preview {
+            let entry = self.entries.iter().find(|e| e.name == *name);
+            if let Some(entry) = entry {
+                let lo = entry.lo.map_or("unknown".to_string(), |lo| format!("{:.6} MHz", lo / 1e6));
+                let fill = entry.fill.map_or("unknown".to_string(), |f| f.to_string());
+                ui.label(format!("{}: {} tones, LO {}, fill {}", name, entry.tones.len(), lo, fill));
+            }
+            Plot::new("dac_library_preview")
+                .height(250.0)
+                .x_axis_label("Frequency Offset (MHz)")
+                .y_axis_label("Power (dB)")
+                .show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(points.clone()).name(name.as_str()));
+                });
+        }
+
+        action
+    }
+}
@@ -0,0 +1,210 @@
+// Resonator frequency drift tracking
+// Repeats targeted sweeps on a schedule, fits f0 for every resonator and flags shifts larger than a fraction of the linewidth
+// Called to in gui
+
+use crate::resonator_fit::fit_resonator;
+use crate::targeted_sweep::{ResonatorSweep, TargetedSweep};
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// One f0 measurement of one resonator
+pub struct DriftPoint {
+    pub time: f64,      // Seconds since tracking started
+    pub f0: f64,        // Fitted resonance frequency (Hz)
+    pub linewidth: f64, // f0 / Qr (Hz)
+}
+
+pub struct DriftTracker {
+    pub running: bool,                          // Tracking sweeps are being scheduled
+    pub interval: String,                       // Seconds between tracking sweeps
+    pub span: String,                           // Window span per resonator (Hz)
+    pub points: String,                         // Points per window
+    pub average: String,                        // Sweep averaging
+    pub threshold: String,                      // Alert when |shift| exceeds this fraction of the linewidth
+    pub in_linewidths: bool,                    // Plot shifts in linewidths instead of kHz
+    pub pending: Option<TargetedSweep>,         // Windows of the tracking sweep in flight
+    pub history: BTreeMap<u32, Vec<DriftPoint>>, // Measurements per resonator ID
+    pub alerts: Vec<String>,                    // Alert log
+    pub message: Option<String>,                // Error message
+    start: Option<Instant>,
+    last_sweep: Option<Instant>,
+}
+
+impl Default for DriftTracker {
+    fn default() -> Self {
+        Self {
+            running: false,
+            interval: "60".to_string(),
+            span: "500000".to_string(),
+            points: "101".to_string(),
+            average: "1".to_string(),
+            threshold: "0.1".to_string(),
+            in_linewidths: false,
+            pending: None,
+            history: BTreeMap::new(),
+            alerts: Vec::new(),
+            message: None,
+            start: None,
+            last_sweep: None,
+        }
+    }
+}
+
+impl DriftTracker {
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.interval.trim().parse::<f64>().unwrap_or(60.0).max(1.0))
+    }
+
+    // Start a new tracking run
+    pub fn start(&mut self) -> Result<(), String> {
+        self.window_settings()?;
+        self.history.clear();
+        self.alerts.clear();
+        self.start = Some(Instant::now());
+        self.last_sweep = None;
+        self.running = true;
+        Ok(())
+    }
+
+    // Span, points per window and averaging
+    pub fn window_settings(&self) -> Result<(f64, usize, u64), String> {
+        match (self.span.trim().parse::<f64>(), self.points.trim().parse::<usize>(), self.average.trim().parse::<u64>()) {
+            (Ok(span), Ok(points), Ok(average)) if span > 0.0 && points > 0 => Ok((span, points, average)),
+            _ => Err("Invalid drift tracking span, number of points or average.".to_string()),
+        }
+    }
+
+    // A tracking sweep is due (and none is in flight)
+    pub fn due(&self) -> bool {
+        self.running && self.pending.is_none() && self.last_sweep.is_none_or(|t| t.elapsed() >= self.interval())
+    }
+
+    // Time until the next tracking sweep, used to schedule a repaint
+    pub fn time_to_next(&self) -> Duration {
+        self.last_sweep.map_or(Duration::ZERO, |t| self.interval().saturating_sub(t.elapsed()))
+    }
+
+    // Remember the windows of the tracking sweep just sent
+    pub fn sent(&mut self, targeted: TargetedSweep) {
+        self.pending = Some(targeted);
+        self.last_sweep = Some(Instant::now());
+    }
+
+    // Fit f0 in every window of a completed tracking sweep; returns alerts raised by this sweep
+    pub fn record(&mut self, sweeps: &[ResonatorSweep]) -> Vec<String> {
+        let time = self.start.map_or(0.0, |s| s.elapsed().as_secs_f64());
+        let threshold = self.threshold.trim().parse::<f64>().unwrap_or(f64::INFINITY);
+        let mut alerts = Vec::new();
+
+        for sweep in sweeps {
+            let (Some(id), Some(trace)) = (sweep.id, sweep.traces.first()) else {
+                continue;
+            };
+            let Ok(fit) = fit_resonator(&trace.freqs, &trace.iq, false) else {
+                continue;
+            };
+            let point = DriftPoint {
+                time,
+                f0: fit.f0,
+                linewidth: fit.f0 / fit.qr,
+            };
+
+            let history = self.history.entry(id).or_default();
+            if let Some(first) = history.first() {
+                let shift = point.f0 - first.f0;
+                if shift.abs() > threshold * point.linewidth {
+                    alerts.push(format!(
+                        "Resonator {} moved {:.1} kHz ({:.2} linewidths) at t = {:.0} s",
+                        id,
+                        shift / 1e3,
+                        shift / point.linewidth,
+                        time
+                    ));
+                }
+            }
+            history.push(point);
+        }
+
+        self.alerts.extend(alerts.iter().cloned());
+        alerts
+    }
+
+    // Most recent fitted f0 of every tracked resonator
+    pub fn latest_f0s(&self) -> BTreeMap<u32, f64> {
+        self.history.iter().filter_map(|(id, points)| Some((*id, points.last()?.f0))).collect()
+    }
+
+    // Draw the drift tracking pane
+    pub fn show(&mut self, ui: &mut egui::Ui, resonator_count: usize) {
+        ui.horizontal(|ui| {
+            ui.label("Interval (s):");
+            ui.text_edit_singleline(&mut self.interval);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Span per Resonator (Hz):");
+            ui.text_edit_singleline(&mut self.span);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Points per Resonator:");
+            ui.text_edit_singleline(&mut self.points);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Average:");
+            ui.text_edit_singleline(&mut self.average);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Alert Threshold (fraction of linewidth):");
+            ui.text_edit_singleline(&mut self.threshold);
+        });
+        ui.label("Tracking sweeps use the board's current attenuations and FFT scale.");
+
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button("Stop Tracking").clicked() {
+                    self.running = false;
+                }
+                ui.label(format!("Tracking {} resonators, next sweep in {:.0} s", resonator_count, self.time_to_next().as_secs_f64()));
+            } else if ui.button("Start Tracking").clicked() {
+                self.message = self.start().err();
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Frequency shift of each resonator relative to its first measurement
+        let in_linewidths = self.in_linewidths;
+        ui.checkbox(&mut self.in_linewidths, "Show shifts in linewidths");
+        Plot::new("drift_plot")
+            .height(300.0)
+            .legend(Legend::default())
+            .x_axis_label("Time (min)")
+            .y_axis_label(if in_linewidths { "Shift (linewidths)" } else { "Shift (kHz)" })
+            .show(ui, |plot_ui| {
+                for (id, points) in &self.history {
+                    let Some(first) = points.first() else {
+                        continue;
+                    };
+                    let line: Vec<[f64; 2]> = points
+                        .iter()
+                        .map(|p| {
+                            let shift = p.f0 - first.f0;
+                            [p.time / 60.0, if in_linewidths { shift / p.linewidth } else { shift / 1e3 }]
+                        })
+                        .collect();
+                    plot_ui.line(Line::new(line).name(format!("Resonator {}", id)));
+                }
+            });
+
+        // Alert log
+        ui.label(format!("Alerts ({}):", self.alerts.len()));
+        egui::ScrollArea::vertical().id_salt("drift_alerts").max_height(150.0).show(ui, |ui| {
+            for alert in self.alerts.iter().rev() {
+                ui.colored_label(egui::Color32::RED, alert);
+            }
+        });
+    }
+}
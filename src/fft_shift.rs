@@ -0,0 +1,104 @@
+// FFT shift schedule editor
+// The DSP scale is a bitmask with one bit per FFT stage; a set bit halves the data after that stage
+// Called to in gui
+
+use crate::gui::VALID_FFT_SCALES;
+use eframe::egui;
+
+// Number of FFT stages, bit 0 is the first stage
+pub const FFT_STAGES: usize = 12;
+
+// Gain of a shift schedule relative to no shifts (dB)
+pub fn gain_db(scale: u16) -> f64 {
+    -20.0 * 2f64.log10() * scale.count_ones() as f64
+}
+
+#[derive(Default)]
+pub struct ShiftEditor {
+    pub stages: [bool; FFT_STAGES],
+    pub message: Option<String>,
+}
+
+impl ShiftEditor {
+    // Scale composed from the stage toggles
+    pub fn value(&self) -> u16 {
+        self.stages.iter().enumerate().filter(|(_, on)| **on).fold(0, |v, (i, _)| v | 1 << i)
+    }
+
+    // Set the toggles from a scale
+    pub fn load(&mut self, scale: u16) {
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            *stage = scale & (1 << i) != 0;
+        }
+    }
+
+    // Valid scale with the same number of shifts, or failing that the fewest differing stages
+    fn nearest_valid(scale: u16) -> u16 {
+        *VALID_FFT_SCALES
+            .iter()
+            .min_by_key(|v| (v.count_ones().abs_diff(scale.count_ones()), (*v ^ scale).count_ones()))
+            .unwrap()
+    }
+
+    // Board reported that the requested scale was clamped
+    pub fn clamped(&mut self, requested: u16, applied: u16) {
+        self.load(applied);
+        self.message = Some(format!(
+            "Board clamped DSP scale {} to {} ({} shifts, {:.1} dB).",
+            requested,
+            applied,
+            applied.count_ones(),
+            gain_db(applied)
+        ));
+    }
+
+    // Draw the editor; current is the board's scale if known. Returns a scale to apply.
+    pub fn show(&mut self, ui: &mut egui::Ui, current: Option<u16>) -> Option<u16> {
+        let mut apply = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Shift after stage:");
+            for (i, stage) in self.stages.iter_mut().enumerate() {
+                ui.checkbox(stage, format!("{}", i + 1));
+            }
+        });
+
+        let value = self.value();
+        let valid = VALID_FFT_SCALES.contains(&value);
+        ui.label(format!(
+            "Scale: {} (0b{:012b}), {} shifts, total gain 2^-{} ({:.1} dB)",
+            value,
+            value,
+            value.count_ones(),
+            value.count_ones(),
+            gain_db(value)
+        ));
+        if !valid {
+            let nearest = Self::nearest_valid(value);
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::RED, format!("Not an allowed schedule; nearest allowed is {} (0b{:012b}).", nearest, nearest));
+                if ui.button("Use Nearest").clicked() {
+                    self.load(nearest);
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(current.is_some(), egui::Button::new("Load Current")).clicked() {
+                if let Some(current) = current {
+                    self.load(current);
+                }
+            }
+            if ui.add_enabled(valid, egui::Button::new("Apply Schedule")).clicked() {
+                apply = Some(value);
+                self.message = None;
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        apply
+    }
+}
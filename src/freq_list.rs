@@ -0,0 +1,366 @@
+// Sweep frequency list generation
+// Builds the Vec<Hertz> used for SweepConfig.freqs (linear, centre/span, step, log, resonator windows, file import)
+// Called to in gui
+
+use eframe::egui;
+use gen3_rpc::Hertz;
+use std::path::Path;
+
+// Most points a generated or imported list may hold
+pub const MAX_FREQ_POINTS: usize = 100_000;
+
+fn too_many() -> String {
+    format!("Invalid input: More than {} frequency points.", MAX_FREQ_POINTS)
+}
+
+// Ways of generating the frequency list
+#[derive(PartialEq, Clone, Copy, Default)]
+pub enum FreqMode {
+    #[default]
+    Linear,     // Start, stop, number of points
+    CenterSpan, // Centre, span, number of points
+    Step,       // Start, stop, step size
+    Log,        // Start, stop, number of logarithmically spaced points
+    Windows,    // Windows around a list of known resonator frequencies
+    Import,     // Read from a CSV or npy file
+}
+
+impl FreqMode {
+    const ALL: [FreqMode; 6] = [
+        FreqMode::Linear,
+        FreqMode::CenterSpan,
+        FreqMode::Step,
+        FreqMode::Log,
+        FreqMode::Windows,
+        FreqMode::Import,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            FreqMode::Linear => "Linear (start/stop/count)",
+            FreqMode::CenterSpan => "Centre + Span",
+            FreqMode::Step => "Step Size",
+            FreqMode::Log => "Logarithmic",
+            FreqMode::Windows => "Resonator Windows",
+            FreqMode::Import => "Import File",
+        }
+    }
+}
+
+// Inputs for every generation mode (Strings to handle text input)
+#[derive(Default)]
+pub struct FreqListBuilder {
+    pub mode: FreqMode,
+    pub start: String,         // Start frequency (Hz)
+    pub stop: String,          // Stop frequency (Hz)
+    pub count: String,         // Number of points (per window in Windows mode)
+    pub center: String,        // Centre frequency (Hz)
+    pub span: String,          // Span (Hz, per window in Windows mode)
+    pub step: String,          // Step size (Hz)
+    pub resonators: String,    // Known resonator frequencies (Hz), separated by commas/whitespace
+    pub import_path: String,   // CSV or npy file to import
+}
+
+impl FreqListBuilder {
+    // Draw the inputs for the selected mode. When from_board is set the start frequency is board_start (the IF frequency).
+    // Returns the generated list (or an error) when "Generate Frequency List" is clicked.
+    pub fn show(&mut self, ui: &mut egui::Ui, from_board: bool, board_start: Option<f64>) -> Option<Result<Vec<Hertz>, String>> {
+        egui::ComboBox::from_label("Spacing")
+            .selected_text(self.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in FreqMode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.name());
+                }
+            });
+
+        let field = |ui: &mut egui::Ui, label: &str, value: &mut String| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                ui.text_edit_singleline(value);
+            });
+        };
+
+        // Start frequency is shared by the modes that sweep between two edges
+        if matches!(self.mode, FreqMode::Linear | FreqMode::Step | FreqMode::Log) && !from_board {
+            field(ui, "Start Frequency (e.g., 6000000000):", &mut self.start);
+        }
+
+        match self.mode {
+            FreqMode::Linear | FreqMode::Log => {
+                field(ui, "Stopping Frequency (e.g., 6020000000):", &mut self.stop);
+                field(ui, "Number of Frequency Values:", &mut self.count);
+            }
+            FreqMode::Step => {
+                field(ui, "Stopping Frequency (e.g., 6020000000):", &mut self.stop);
+                field(ui, "Step Size (Hz):", &mut self.step);
+            }
+            FreqMode::CenterSpan => {
+                field(ui, "Centre Frequency (Hz):", &mut self.center);
+                field(ui, "Span (Hz):", &mut self.span);
+                field(ui, "Number of Frequency Values:", &mut self.count);
+            }
+            FreqMode::Windows => {
+                ui.label("Resonator Frequencies (Hz, separated by commas or new lines):");
+                ui.add(egui::TextEdit::multiline(&mut self.resonators).desired_rows(3));
+                field(ui, "Span per Window (Hz):", &mut self.span);
+                field(ui, "Points per Window:", &mut self.count);
+            }
+            FreqMode::Import => {
+                field(ui, "File (.csv or .npy):", &mut self.import_path);
+            }
+        }
+
+        if ui.button("Generate Frequency List").clicked() {
+            Some(self.generate(from_board, board_start))
+        } else {
+            None
+        }
+    }
+
+    // Generate the frequency list for the selected mode
+    pub fn generate(&self, from_board: bool, board_start: Option<f64>) -> Result<Vec<Hertz>, String> {
+        let start = || {
+            if from_board {
+                board_start.ok_or_else(|| "Invalid input: Initial frequency not available from board.".to_string())
+            } else {
+                parse_f64(&self.start, "start frequency")
+            }
+        };
+
+        let freqs = match self.mode {
+            FreqMode::Linear => linear(start()?, parse_f64(&self.stop, "stopping frequency")?, parse_count(&self.count)?)?,
+            FreqMode::CenterSpan => {
+                let center = parse_f64(&self.center, "centre frequency")?;
+                let span = parse_f64(&self.span, "span")?;
+                if span < 0.0 {
+                    return Err("Invalid input: Span must be >= 0.".to_string());
+                }
+                linear(center - span / 2.0, center + span / 2.0, parse_count(&self.count)?)?
+            }
+            FreqMode::Step => stepped(start()?, parse_f64(&self.stop, "stopping frequency")?, parse_f64(&self.step, "step size")?)?,
+            FreqMode::Log => logarithmic(start()?, parse_f64(&self.stop, "stopping frequency")?, parse_count(&self.count)?)?,
+            FreqMode::Windows => {
+                let centers = parse_list(&self.resonators)?;
+                windows(&centers, parse_f64(&self.span, "span")?, parse_count(&self.count)?)?
+            }
+            FreqMode::Import => import(Path::new(self.import_path.trim()))?,
+        };
+
+        Ok(freqs.into_iter().map(to_hertz).collect())
+    }
+}
+
+// Round a frequency to integer Hz
+pub fn to_hertz(freq: f64) -> Hertz {
+    Hertz::new(freq.round() as i64, 1)
+}
+
+fn parse_f64(value: &str, name: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("Invalid input: Enter a valid number for the {}.", name))
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(count) if count > MAX_FREQ_POINTS => Err(too_many()),
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err("Invalid input: Number of frequency values must be >= 1.".to_string()),
+    }
+}
+
+// Parse frequencies separated by commas and/or whitespace
+fn parse_list(value: &str) -> Result<Vec<f64>, String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| parse_f64(s, "resonator frequency list"))
+        .collect()
+}
+
+// Evenly spaced points from start to stop (inclusive); a single point sits at start
+pub fn linear(start: f64, stop: f64, count: usize) -> Result<Vec<f64>, String> {
+    if start > stop {
+        return Err("Invalid input: Initial frequency must be <= stopping frequency.".to_string());
+    }
+    if count > MAX_FREQ_POINTS {
+        return Err(too_many());
+    }
+    if count == 1 {
+        return Ok(vec![start]);
+    }
+    Ok((0..count)
+        .map(|i| start + i as f64 * (stop - start) / (count - 1) as f64)
+        .collect())
+}
+
+// Points from start to stop spaced by step (stop included when it lands on a step)
+pub fn stepped(start: f64, stop: f64, step: f64) -> Result<Vec<f64>, String> {
+    if step <= 0.0 {
+        return Err("Invalid input: Step size must be > 0.".to_string());
+    }
+    if start > stop {
+        return Err("Invalid input: Initial frequency must be <= stopping frequency.".to_string());
+    }
+    // Check the count before converting so tiny steps can't saturate or exhaust memory
+    let steps = ((stop - start) / step + 1e-9).floor();
+    if !steps.is_finite() || steps + 1.0 > MAX_FREQ_POINTS as f64 {
+        return Err(too_many());
+    }
+    let count = steps as usize + 1;
+    Ok((0..count).map(|i| start + i as f64 * step).collect())
+}
+
+// Logarithmically spaced points from start to stop (inclusive)
+pub fn logarithmic(start: f64, stop: f64, count: usize) -> Result<Vec<f64>, String> {
+    if start <= 0.0 {
+        return Err("Invalid input: Logarithmic spacing needs a start frequency > 0.".to_string());
+    }
+    if !(stop > 0.0 && stop >= start) {
+        return Err("Invalid input: Initial frequency must be <= stopping frequency.".to_string());
+    }
+    Ok(linear(start.ln(), stop.ln(), count)?.into_iter().map(f64::exp).collect())
+}
+
+// A window of count points and the given span centred on each frequency, merged into one sorted list
+pub fn windows(centers: &[f64], span: f64, count: usize) -> Result<Vec<f64>, String> {
+    if centers.is_empty() {
+        return Err("Invalid input: Enter at least one resonator frequency.".to_string());
+    }
+    if span < 0.0 {
+        return Err("Invalid input: Span must be >= 0.".to_string());
+    }
+    if centers.len().saturating_mul(count) > MAX_FREQ_POINTS {
+        return Err(too_many());
+    }
+    let mut freqs = Vec::with_capacity(centers.len() * count);
+    for &center in centers {
+        freqs.extend(linear(center - span / 2.0, center + span / 2.0, count)?);
+    }
+    freqs.sort_by(f64::total_cmp);
+    freqs.dedup_by(|a, b| a.round() == b.round()); // Overlapping windows would repeat integer-Hz points
+    Ok(freqs)
+}
+
+// Read a frequency list (Hz) from a .npy file (1-D float array) or a CSV file (first column, header lines skipped).
+// Non-finite values are dropped and the list is sorted and deduplicated, as the sweep windows assume.
+pub fn import(path: &Path) -> Result<Vec<f64>, String> {
+    let is_npy = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("npy"));
+    let freqs = if is_npy {
+        let array: ndarray::Array1<f64> = ndarray_npy::read_npy(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        array.to_vec()
+    } else {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        text.lines()
+            .filter_map(|line| line.split([',', ';', '\t']).next())
+            .filter_map(|first| first.trim().parse::<f64>().ok())
+            .collect()
+    };
+
+    let mut freqs: Vec<f64> = freqs.into_iter().filter(|f| f.is_finite()).collect();
+    freqs.sort_by(f64::total_cmp);
+    freqs.dedup_by(|a, b| a.round() == b.round()); // The list is rounded to integer Hz
+    if freqs.is_empty() {
+        return Err(format!("No frequencies found in {}.", path.display()));
+    }
+    if freqs.len() > MAX_FREQ_POINTS {
+        return Err(too_many());
+    }
+    Ok(freqs)
+}
+
+// Start, stop, step, count and decimated preview points of a frequency list, rebuilt only when the list changes
+pub struct FreqSummary {
+    key: (usize, Hertz, Hertz), // Length, first and last value of the summarised list
+    first: f64,
+    last: f64,
+    step: String,
+    points: Vec<[f64; 2]>, // Preview points (index, MHz)
+}
+
+impl FreqSummary {
+    fn new(freqs: &[Hertz]) -> Option<Self> {
+        let values: Vec<f64> = freqs.iter().map(crate::sweep_data::hertz_to_f64).collect();
+        let (Some(&first), Some(&last)) = (values.first(), values.last()) else {
+            return None;
+        };
+
+        // Step range (a single value for evenly spaced lists)
+        let steps = values.windows(2).map(|w| w[1] - w[0]);
+        let (min_step, max_step) = steps.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), s| (lo.min(s), hi.max(s)));
+        let step = if values.len() < 2 {
+            "-".to_string()
+        } else if (max_step - min_step).abs() < 0.5 {
+            format!("{} Hz", min_step)
+        } else {
+            format!("{} to {} Hz (non-uniform)", min_step, max_step)
+        };
+
+        // Preview points (index vs frequency), decimated to keep very long lists cheap to draw
+        let stride = (values.len() / 5000).max(1);
+        let points = values.iter().enumerate().step_by(stride).map(|(i, f)| [i as f64, f / 1e6]).collect();
+        Some(Self {
+            key: (freqs.len(), freqs[0], freqs[freqs.len() - 1]),
+            first,
+            last,
+            step,
+            points,
+        })
+    }
+}
+
+// Summarise a frequency list: start, stop, step and count, a scrolling table and a preview plot.
+// The summary is cached between frames and only the visible table rows are laid out, so lists with many
+// thousands of points stay responsive.
+pub fn show_summary(ui: &mut egui::Ui, freqs: &[Hertz], cache: &mut Option<FreqSummary>) {
+    let (Some(first), Some(last)) = (freqs.first(), freqs.last()) else {
+        return;
+    };
+    if cache.as_ref().is_none_or(|c| c.key != (freqs.len(), *first, *last)) {
+        *cache = FreqSummary::new(freqs);
+    }
+    let Some(summary) = cache.as_ref() else {
+        return;
+    };
+
+    egui::Grid::new("freq_list_summary").show(ui, |ui| {
+        ui.label("Start:");
+        ui.label(format!("{} Hz", summary.first));
+        ui.end_row();
+        ui.label("Stop:");
+        ui.label(format!("{} Hz", summary.last));
+        ui.end_row();
+        ui.label("Step:");
+        ui.label(&summary.step);
+        ui.end_row();
+        ui.label("Count:");
+        ui.label(freqs.len().to_string());
+        ui.end_row();
+    });
+
+    egui_plot::Plot::new("freq_list_preview")
+        .height(150.0)
+        .x_axis_label("Index")
+        .y_axis_label("Frequency (MHz)")
+        .show(ui, |plot_ui| {
+            plot_ui.points(egui_plot::Points::new(summary.points.clone()).radius(1.5));
+        });
+
+    // Detail table, only the visible rows are drawn
+    egui::CollapsingHeader::new("Frequency Table")
+        .id_salt("freq_list_table")
+        .show(ui, |ui| {
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            egui::ScrollArea::vertical()
+                .id_salt("freq_list_scroll")
+                .max_height(200.0)
+                .show_rows(ui, row_height, freqs.len(), |ui, rows| {
+                    for i in rows {
+                        ui.label(format!("{}: {}/{}", i, freqs[i].numer(), freqs[i].denom()));
+                    }
+                });
+        });
+}
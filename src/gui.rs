@@ -4,16 +4,44 @@
 // Called to in main
 
 // Importing crates/modules
+use crate::auto_scale::AutoScale;
+use crate::board_state::BoardStateMonitor;
+use crate::dac_comb::{self, DacComb, DacTable};
+use crate::dac_library::{DacLibrary, LibraryAction};
+use crate::drift::DriftTracker;
+use crate::fft_shift::ShiftEditor;
+use crate::freq_list::{self, FreqListBuilder, FreqSummary};
+use crate::history::History;
+use crate::live_capture::LiveCapturePane;
+use crate::iq_loop::LoopCalibrator;
 use crate::logger::Logger;
+use crate::noise::{NoiseMeasurement, NoiseMetadata};
+use crate::optimal_filter::FilterBuilder;
+use crate::power_map::PowerMap;
+use crate::power_sweep::PowerTable;
+use crate::pulses::PulseDetector;
+use crate::resonator_finder::ResonatorFinder;
+use crate::resonator_fit::ResonatorFitter;
+use crate::resonators::ResonatorList;
+use crate::retune::{self, F0Source, RetuneAction, Retuner};
+use crate::saturation::{self, SaturationMonitor, SaturationReport, Stage};
 use crate::status::Status;
-use crate::worker::{RPCCommand, RPCResponse};
+use crate::sweep_data::{self, SweepTrace};
+use crate::targeted_sweep::{ResonatorSweep, TargetedSweep};
+use crate::timestream::Timestream;
+use crate::verify::VerifyLog;
+use crate::waterfall::Waterfall;
+use crate::worker::{BoardWrite, RPCCommand, RPCResponse};
 use eframe::{egui, App, CreationContext, NativeOptions};
-use num::Complex;
+use std::collections::BTreeMap;
 use std::process::Command; 
 use std::sync::mpsc::{Receiver, Sender};
-use gen3_rpc::{Hertz, Attens}; 
+use gen3_rpc::{Hertz, Attens, Snap}; 
 use gen3_rpc::utils::client::{PowerSetting, SweepConfig}; 
-use std::time::{SystemTime, Duration};
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
+
+// FFT scale values accepted by the board
+pub const VALID_FFT_SCALES: [u16; 13] = [4095, 3967, 1919, 1911, 1879, 1877, 1365, 1301, 277, 273, 257, 1, 0];
 
 // Defining structs
 pub struct MyApp {
@@ -26,18 +54,49 @@ pub struct MyApp {
     command: Sender<RPCCommand>,
     response: Receiver<RPCResponse>,
     error_message: Option<String>, // Error message
-    dac_table: Option<Box<[Complex<i16>; 524288]>>, // DAC table
+    dac_table: Option<DacTable>, // DAC table, shared with the worker's cache
     if_freq: Option<Hertz>, // IF frequency
     if_attens: Option<Attens>, // Attenuations
+    board_fft_scale: Option<u16>, // DSP scale last read from or applied to the board
     connection_time: Option<SystemTime>, // Connection timestamp
-    sweep_start_freq: String, // Input for the starting frequency
-    sweep_stop_freq: String,  // Input for the stopping frequency
-    sweep_count: String,      // Input for the total number of counts in frequency list
+    freq_builder: FreqListBuilder, // Inputs for generating the frequency list
     sweep_freqs: Vec<Hertz>,  // Generated list of frequencies
+    freq_summary: Option<FreqSummary>, // Cached summary of sweep_freqs
     sweep_input_atten: String, // Input attenuation (input)
     sweep_output_atten: String, // Output attenuation (input)
     sweep_dsp_scale: String,   // Input for DSP scale
     sweep_average: String,    // Input for the average value
+    power_table: PowerTable,  // Power settings table (multi-power sweeps)
+    sweep_traces: Vec<SweepTrace>, // Last sweep, one trace per power setting
+    power_map: PowerMap,      // Power sweep heatmap and readout power selection
+    resonator_finder: ResonatorFinder, // Resonator detection on the last sweep
+    resonator_fitter: ResonatorFitter, // Resonator model fits of the detected resonances
+    resonator_list: ResonatorList, // Our resonators
+    dac_comb: Option<DacComb>, // DAC table generated from the resonator list
+    pending_comb: Option<(u64, Option<DacComb>)>, // Comb to adopt once the table with this hash is applied
+    dac_fill: String,         // Fraction of the DAC range used by the generated comb
+    targeted_span: String,    // Window span per resonator for targeted sweeps (Hz)
+    targeted_points: String,  // Points per resonator window for targeted sweeps
+    targeted_sweep: Option<TargetedSweep>, // Windows of the current frequency list, if it is a targeted sweep
+    resonator_sweeps: Vec<ResonatorSweep>, // Last targeted sweep split per resonator
+    drift: DriftTracker,      // Scheduled resonator drift tracking
+    sweeps_in_flight: usize,  // Sweeps sent to the worker and not yet answered
+    retuner: Retuner,         // Comb retuning to fitted f0 with undo
+    loop_calibrator: LoopCalibrator, // IQ loop centre/rotation calibration
+    timestream: Timestream,   // Per-channel phase timestreams
+    pulse_detector: PulseDetector, // Photon pulses found in the timestreams
+    pulse_capture: bool,      // The timestream capture in flight was requested by the pulse pane
+    noise: NoiseMeasurement,  // Averaged phase/dissipation noise PSDs
+    filter_builder: FilterBuilder, // Optimal filters from pulse templates and noise
+    live_capture: LiveCapturePane, // Continuous raw IQ capture
+    waterfall: Waterfall,     // Spectrogram of successive raw IQ captures
+    saturation: SaturationMonitor, // ADC clipping and DSP overflow of every capture
+    auto_scale: AutoScale,    // FFT scale scan and selection
+    shift_editor: ShiftEditor, // Per-stage FFT shift toggles
+    board_state: BoardStateMonitor, // Periodic board reads and changes made outside the GUI
+    verify_log: VerifyLog,    // Read-back verification of set commands
+    dac_library: DacLibrary,  // Saved DAC tables
+    history: History,         // Applied board writes with undo/redo
     sweep_result: Option<String>, // Display Sweep results (non-functional!!!)
 }
 
@@ -53,6 +112,19 @@ enum Pane {
     DACTable, 
     IFBoard, 
     Sweep, 
+    PowerMap,
+    ResonatorFinder,
+    ResonatorFit,
+    Resonators,
+    Drift,
+    IQLoops,
+    Timestream,
+    Pulses,
+    Noise,
+    OptimalFilter,
+    LiveCapture,
+    Waterfall,
+    History,
 }
 
 #[derive(Default)]
@@ -69,22 +141,35 @@ struct Settings {
 // Defining each gui pane/clickable functionality
 impl App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if let Ok(c) = self.response.try_recv() {
+        // Handle every response that arrived since the last frame so live captures cannot queue up
+        while let Ok(c) = self.response.try_recv() {
             match c {
                 // Handle the CaptureResult response
                 RPCResponse::CaptureResult(data) => {
                     if data.is_empty() {
                         self.error_message = Some("Capture failed: No data available.".to_string());
                     } else {
-                        self.sweep_result = Some(format!("Captured Data: {:?}", data));
+                        self.sweep_result = Some(format!("Captured {} samples (plotted in the Live Capture pane).", data.len()));
+                        self.record_saturation(saturation::analyse(Stage::Adc, &data));
+                        self.waterfall.push(&data);
+                        self.live_capture.show_capture(data);
                     }
                 }
                 // Update the FFT scale in the settings
                 RPCResponse::FFTScale(i) => {
                     self.settings.fft_scale = i.map_or_else(|| "0".to_string(), |v| v.to_string());
+                    self.board_fft_scale = i;
+                }
+                RPCResponse::FFTScaleClamped(requested, clamped) => {
+                    self.settings.fft_scale = clamped.to_string();
+                    self.board_fft_scale = Some(clamped);
+                    self.shift_editor.clamped(requested, clamped);
                 }
                 // Update the DAC table
                 RPCResponse::DACTable(d) => {
+                    if d.is_some() {
+                        self.dac_library.board_table(d.as_ref());
+                    }
                     self.dac_table = d;
                 }
                 // Update the IF frequency
@@ -95,17 +180,169 @@ impl App for MyApp {
                 RPCResponse::IFAttens(a) => {
                     self.if_attens = a;
                 }
+                // Log writes for undo/redo
+                RPCResponse::Applied(write) => {
+                    if let BoardWrite::FFTScale(scale) = write.after {
+                        self.board_fft_scale = Some(scale);
+                    }
+                    if let BoardWrite::DACTable(ref table, hash) = write.after {
+                        if let Some(comb) = self.retuner.applied(&mut self.resonator_list, hash) {
+                            self.dac_comb = comb;
+                        }
+                        if let Some((pending, comb)) = self.pending_comb.take() {
+                            if pending == hash {
+                                self.dac_comb = comb;
+                            }
+                        }
+                        // Undo/redo or another client replaced the table, use the library's comb for it if it has one
+                        if self.dac_comb.as_ref().is_none_or(|c| dac_comb::table_hash(&c.table[..]) != hash) {
+                            self.dac_comb = self.dac_library.comb_for(table, hash);
+                        }
+                    }
+                    self.history.record(write);
+                }
+                // End a pending undo/redo, retune or library apply only when its own set command failed
+                RPCResponse::WriteFailed(write) => {
+                    if let BoardWrite::DACTable(_, hash) = write {
+                        self.retuner.failed(Some(hash));
+                        if self.pending_comb.as_ref().is_some_and(|(pending, _)| *pending == hash) {
+                            self.pending_comb = None;
+                        }
+                    }
+                    self.history.failed(&write);
+                }
+                // Report read-back mismatches
+                RPCResponse::Verified(verification) => {
+                    if let Some(mismatch) = self.verify_log.receive(verification) {
+                        self.error_message = Some(mismatch);
+                    }
+                }
+                // Adopt the state read from the board
+                RPCResponse::BoardState(state) => {
+                    self.board_state.receive(&state);
+                    // The scale text box is left alone, it holds the user's next entry
+                    if state.fft_scale.is_some() {
+                        self.board_fft_scale = state.fft_scale;
+                    }
+                    if state.if_freq.is_some() {
+                        self.if_freq = state.if_freq;
+                    }
+                    if state.if_attens.is_some() {
+                        self.if_attens = state.if_attens;
+                    }
+                    if state.dac_table.is_some() {
+                        self.dac_library.board_table(state.dac_table.as_ref());
+                        self.dac_table = state.dac_table;
+                    }
+                }
                 // Update the connection status
                 RPCResponse::Connected(time) => {
                     self.status.update("Connected");
                     self.connection_time = Some(time);
                 }
                 RPCResponse::Sweep(sweep) => {
-                    self.sweep_result = Some(format!("{:?}", sweep));
+                    self.sweeps_in_flight = self.sweeps_in_flight.saturating_sub(1);
+                    // Drift tracking sweeps are only sent when no other sweep is in flight
+                    let tracking = self.drift.pending.take();
+                    match (sweep, tracking) {
+                        (Some(sweep), Some(targeted)) => {
+                            let traces = sweep_data::traces(&sweep);
+                            for alert in self.drift.record(&targeted.split(&traces)) {
+                                self.status.update(&alert);
+                            }
+                        }
+                        (Some(sweep), None) => {
+                            self.sweep_traces = sweep_data::traces(&sweep);
+                            self.sweep_result = Some(sweep_data::summary(&self.sweep_traces));
+                            self.power_map.set_traces(&self.sweep_traces);
+                            self.resonator_sweeps = match self.targeted_sweep {
+                                Some(ref targeted) => targeted.split(&self.sweep_traces),
+                                None => Vec::new(),
+                            };
+                        }
+                        (None, Some(_)) => {
+                            self.drift.message = Some("Drift tracking sweep failed.".to_string());
+                        }
+                        (None, None) => {
+                            self.error_message = Some("Sweep failed.".to_string());
+                        }
+                    }
+                }
+                RPCResponse::FFTScaleScan(captures) => {
+                    if let Some(scale) = self.auto_scale.receive(captures) {
+                        if let Err(e) = set_scale(&self.command, scale) {
+                            self.auto_scale.message = Some(format!("Failed to set scale: {}", e));
+                        }
+                    }
+                }
+                RPCResponse::LiveFrame(frame) => {
+                    if !frame.data.is_empty() {
+                        self.record_saturation(saturation::analyse(Stage::Adc, &frame.data));
+                    }
+                    self.waterfall.push(&frame.data);
+                    self.live_capture.receive(frame);
+                }
+                RPCResponse::ChannelCapture(snap) => {
+                    let failed = snap.is_none();
+                    // Phase samples are wrapped angles, only channelized IQ can show overflow
+                    if let Some(Snap::DdcIQ(ref channels)) = snap {
+                        self.record_saturation(saturation::analyse(Stage::Dsp, channels.iter().flatten()));
+                    }
+                    // Calibrations follow the resonators the comb was generated for, not the list as edited since
+                    let cals: Vec<_> = self.dac_comb.as_ref().map_or_else(Vec::new, |comb| {
+                        comb.channel_resonators()
+                            .into_iter()
+                            .map(|id| self.resonator_list.resonators.iter().find(|r| Some(r.id) == id).and_then(|r| r.loop_cal))
+                            .collect()
+                    });
+                    self.timestream.receive(snap, &cals);
+                    // Pulse detection only sees its own captures, not plain timestream or noise ones
+                    if std::mem::take(&mut self.pulse_capture) {
+                        self.pulse_detector.process(&self.timestream.latest);
+                    }
+                    if self.noise.running && failed {
+                        self.noise.abort();
+                    } else if self.noise.running {
+                        self.noise.process(&self.timestream.latest);
+                    }
                 }
             }
         }
 
+        // Schedule drift tracking sweeps
+        if self.drift.running {
+            if self.drift.due() && self.sweeps_in_flight == 0 {
+                self.send_drift_sweep();
+            }
+            ctx.request_repaint_after(self.drift.time_to_next().max(Duration::from_millis(100)));
+        }
+
+        self.live_capture.update(ctx);
+
+        // Rolling live timestream, pulse and noise captures
+        if self.timestream.live || self.noise.running || self.pulse_detector.live {
+            if !self.timestream.in_flight {
+                self.pulse_capture = self.pulse_detector.live && !self.noise.running;
+                self.send_timestream_capture();
+            }
+            ctx.request_repaint();
+        }
+
+        // Status bar with saturation warnings
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(&self.status.status_message);
+                if let Some(warning) = self.saturation.warning() {
+                    ui.separator();
+                    ui.colored_label(egui::Color32::RED, warning);
+                }
+                if let Some(warning) = self.board_state.warning() {
+                    ui.separator();
+                    ui.colored_label(egui::Color32::RED, warning);
+                }
+            });
+        });
+
         egui::SidePanel::left("side_panel").show(ctx, |ui| {
             ui.heading("Menu");
 
@@ -133,6 +370,45 @@ impl App for MyApp {
             if ui.button("Sweep").clicked() {
                 self.current_pane = Pane::Sweep;
             }
+            if ui.button("Power Sweep Map").clicked() {
+                self.current_pane = Pane::PowerMap;
+            }
+            if ui.button("Resonator Finder").clicked() {
+                self.current_pane = Pane::ResonatorFinder;
+            }
+            if ui.button("Resonator Fitting").clicked() {
+                self.current_pane = Pane::ResonatorFit;
+            }
+            if ui.button("Resonators").clicked() {
+                self.current_pane = Pane::Resonators;
+            }
+            if ui.button("Drift Tracking").clicked() {
+                self.current_pane = Pane::Drift;
+            }
+            if ui.button("IQ Loop Calibration").clicked() {
+                self.current_pane = Pane::IQLoops;
+            }
+            if ui.button("Timestream").clicked() {
+                self.current_pane = Pane::Timestream;
+            }
+            if ui.button("Pulse Detection").clicked() {
+                self.current_pane = Pane::Pulses;
+            }
+            if ui.button("Noise").clicked() {
+                self.current_pane = Pane::Noise;
+            }
+            if ui.button("Optimal Filters").clicked() {
+                self.current_pane = Pane::OptimalFilter;
+            }
+            if ui.button("Live Capture").clicked() {
+                self.current_pane = Pane::LiveCapture;
+            }
+            if ui.button("Waterfall").clicked() {
+                self.current_pane = Pane::Waterfall;
+            }
+            if ui.button("History").clicked() {
+                self.current_pane = Pane::History;
+            }
         });
 
         // Showing the central pane selected
@@ -194,6 +470,22 @@ impl App for MyApp {
                 Pane::Status => {
                     ui.heading("Status");
                     ui.label(&self.status.status_message);
+
+                    ui.separator();
+                    ui.heading("Board State");
+                    if let Some(command) = self.board_state.show(ui) {
+                        self.command.send(command).unwrap();
+                    }
+
+                    ui.separator();
+                    ui.heading("Set Verification");
+                    if let Some(enabled) = self.verify_log.show(ui) {
+                        self.command.send(RPCCommand::SetVerify(enabled)).unwrap();
+                    }
+
+                    ui.separator();
+                    ui.heading("Saturation");
+                    self.saturation.show(ui);
                 }
                 Pane::DSPScale => {
                     ui.heading("DSP Scale");
@@ -204,7 +496,7 @@ impl App for MyApp {
                     }
 
                     // Display the current DSP scale if available
-                    ui.label(format!("DSP Scale: {}", self.settings.fft_scale));
+                    ui.label(format!("DSP Scale: {}", self.board_fft_scale.map_or("unknown".to_string(), |s| s.to_string())));
 
                     // Text input for adjusting scale
                     ui.horizontal(|ui| {
@@ -217,10 +509,9 @@ impl App for MyApp {
 
                     // Button to apply scale
                     if ui.button("Apply Scale").clicked() {
-                        let valid_values = vec![4095, 3967, 1919, 1911, 1879, 1877, 1365, 1301, 277, 273, 257, 1, 0];
                         if let Ok(scale_value) = self.settings.fft_scale.parse::<u16>() {
                             // Only pass the value to the worker.rs if it is within the accepted values
-                            if valid_values.contains(&scale_value) {
+                            if VALID_FFT_SCALES.contains(&scale_value) {
                                 if let Err(e) = set_scale(&self.command, scale_value) {
                                     self.error_message = Some(format!("Failed to set scale: {}", e));
                                 } else {
@@ -236,6 +527,22 @@ impl App for MyApp {
                         }
                     }
 
+                    // Compose the scale from per-stage shifts
+                    ui.separator();
+                    if let Some(scale) = self.shift_editor.show(ui, self.board_fft_scale) {
+                        if let Err(e) = set_scale(&self.command, scale) {
+                            self.shift_editor.message = Some(format!("Failed to set scale: {}", e));
+                        }
+                    }
+
+                    // Scan every valid scale and apply the best one
+                    ui.separator();
+                    ui.label("Auto Scale: capture DDC IQ of the comb's first channels at every valid scale and apply the one with the highest peak that does not clip.");
+                    let tones = self.dac_comb.as_ref().map_or(0, |comb| comb.tones.len());
+                    if let Some(command) = self.auto_scale.show(ui, tones) {
+                        self.command.send(command).unwrap();
+                    }
+
                     // Display possible error
                     if let Some(ref error_message) = self.error_message {
                         ui.label(error_message);
@@ -255,23 +562,84 @@ impl App for MyApp {
                         ui.label(format!("DAC Table: {:?}", &dac_table[..16]));
                     }
 
-                    // Text input for setting DAC table
+                    // Generate a tone comb from the enabled resonators in the resonator list
                     ui.horizontal(|ui| {
-                        ui.label("DAC Table:");
-                        ui.text_edit_singleline(&mut self.settings.fft_scale); // Use a different field for DAC table input
+                        ui.label("Fill (fraction of full scale):");
+                        ui.text_edit_singleline(&mut self.dac_fill);
                     });
+                    if ui.button("Generate From Resonator List").clicked() {
+                        match (self.if_freq.as_ref(), self.dac_fill.parse::<f64>()) {
+                            (Some(lo), Ok(fill)) => {
+                                match dac_comb::generate(&self.resonator_list.tones(), sweep_data::hertz_to_f64(lo), fill) {
+                                    Ok(comb) => {
+                                        self.dac_comb = Some(comb);
+                                        self.error_message = None;
+                                    }
+                                    Err(e) => self.error_message = Some(e),
+                                }
+                            }
+                            (None, _) => self.error_message = Some("IF frequency unknown. Get the IF frequency first.".to_string()),
+                            (_, Err(_)) => self.error_message = Some("Invalid fill value.".to_string()),
+                        }
+                    }
+                    if let Some(ref comb) = self.dac_comb {
+                        ui.label(format!("Generated comb: {} tones", comb.tones.len()));
+                    }
 
-                    // Button to set the DAC table
+                    // Button to set the DAC table to the generated comb
                     if ui.button("Set DAC Table").clicked() {
-                        // PArse input and set DAC table
-                        let data: Box<[Complex<i16>; 524288]> = Box::new([Complex::new(0, 0); 524288]); // Replace with actual user input parsing
-                        if let Err(e) = set_dac_table(&self.command, data) {
-                            self.error_message = Some(format!("Failed to set DAC table: {}", e));
+                        if let Some(ref comb) = self.dac_comb {
+                            if let Err(e) = set_dac_table(&self.command, comb.table.clone()) {
+                                self.error_message = Some(format!("Failed to set DAC table: {}", e));
+                            } else {
+                                self.error_message = None; // Clear the error message on success
+                            }
                         } else {
-                            self.error_message = None; // Clear the error message on success
+                            self.error_message = Some("Generate a DAC table first.".to_string());
                         }
                     }
 
+                    // Retune the comb to fresh fitted f0 values
+                    ui.group(|ui| {
+                        ui.label("Retune");
+                        let drift_f0s = self.drift.latest_f0s();
+                        match self.retuner.show(ui, self.resonator_fitter.fits.len(), drift_f0s.len()) {
+                            Some(RetuneAction::Retune) => self.retune(drift_f0s),
+                            Some(RetuneAction::Undo) => {
+                                if let Some(table) = self.retuner.undo() {
+                                    self.retuner.message = Some(match set_dac_table(&self.command, table) {
+                                        Ok(()) => "Restoring the previous DAC table.".to_string(),
+                                        Err(e) => {
+                                            self.retuner.failed(None);
+                                            format!("Failed to set DAC table: {}", e)
+                                        }
+                                    });
+                                }
+                            }
+                            None => {}
+                        }
+                    });
+
+                    // Saved tables
+                    ui.group(|ui| {
+                        ui.label("Library");
+                        match self.dac_library.show(ui, self.dac_table.as_ref()) {
+                            Some(LibraryAction::Save) => self.save_dac_table(),
+                            Some(LibraryAction::Apply(table)) => {
+                                // The table's comb is adopted once the board has applied it
+                                self.pending_comb = Some((dac_comb::table_hash(&table.table[..]), table.comb()));
+                                self.dac_library.message = Some(match set_dac_table(&self.command, table.table) {
+                                    Ok(()) => "Applying table to the board.".to_string(),
+                                    Err(e) => {
+                                        self.pending_comb = None;
+                                        format!("Failed to set DAC table: {}", e)
+                                    }
+                                });
+                            }
+                            None => {}
+                        }
+                    });
+
                     // Display the error message if it exists
                     if let Some(ref error_message) = self.error_message {
                         ui.label(error_message);
@@ -377,13 +745,8 @@ impl App for MyApp {
                             }
                         });
 
-                        // Handle manual input or fetching frequency from the board
-                        if self.settings.if_freq_mode == "Manual" {
-                            ui.horizontal(|ui| {
-                                ui.label("Start Frequency (e.g., 6000000000):");
-                                ui.text_edit_singleline(&mut self.sweep_start_freq);
-                            });
-                        } else if self.settings.if_freq_mode == "Board" {
+                        // Handle fetching frequency from the board
+                        if self.settings.if_freq_mode == "Board" {
                             if ui.button("Get Frequency from Board").clicked() {
                                 self.command.send(RPCCommand::GetIFFreq).unwrap();
                             }
@@ -395,55 +758,63 @@ impl App for MyApp {
                             }
                         }
 
-                        // Show stopping frequency and number of counts
+                        // Show the frequency list inputs for the selected spacing
                         if self.settings.if_freq_mode == "Manual" || self.settings.if_freq_mode == "Board" {
-                            ui.horizontal(|ui| {
-                                ui.label("Stopping Frequency (e.g., 6020000000):");
-                                ui.text_edit_singleline(&mut self.sweep_stop_freq);
-                            });
-
-                            ui.horizontal(|ui| {
-                                ui.label("Number of Frequency Values:");
-                                ui.text_edit_singleline(&mut self.sweep_count);
-                            });
-
-                            // Button to generate frequencies
-                            if ui.button("Generate Frequency List").clicked() {
-                                let start_freq = if self.settings.if_freq_mode == "Manual" {
-                                    self.sweep_start_freq.parse::<i64>().ok()
-                                } else {
-                                    self.if_freq.as_ref().map(|f| f.numer()).cloned()
-                                };
-
-                                if let (Some(start), Ok(stop), Ok(count)) = (
-                                    start_freq,
-                                    self.sweep_stop_freq.parse::<i64>(),
-                                    self.sweep_count.parse::<usize>(),
-                                ) {
-                                    if count > 1 && start < stop {
-                                        self.sweep_freqs = (0..count)
-                                            .map(|i| {
-                                                let freq = start + i as i64 * (stop - start) / (count as i64 - 1);
-                                                Hertz::new(freq, 1)
-                                            })
-                                            .collect();
-                                        self.error_message = None; // Clear any previous error messages
-                                    } else {
-                                        self.error_message = Some("Invalid input: Count must be > 1 and initial frequency < stopping frequency.".to_string());
-                                    }
-                                } else {
-                                    self.error_message = Some("Invalid input: Enter valid numbers for initial/stopping frequency and count.".to_string());
+                            let from_board = self.settings.if_freq_mode == "Board";
+                            let board_start = self.if_freq.as_ref().map(sweep_data::hertz_to_f64);
+
+                            // Generate frequencies when the button is clicked
+                            match self.freq_builder.show(ui, from_board, board_start) {
+                                Some(Ok(freqs)) => {
+                                    self.sweep_freqs = freqs;
+                                    self.targeted_sweep = None;
+                                    self.error_message = None; // Clear any previous error messages
                                 }
+                                Some(Err(e)) => self.error_message = Some(e),
+                                None => {}
                             }
 
-                            // Display the generated frequencies
+                            // Display a summary of the generated frequencies
                             if !self.sweep_freqs.is_empty() {
                                 ui.label("Generated Frequency List:");
-                                for freq in &self.sweep_freqs {
-                                    ui.label(format!("{}/{}", freq.numer(), freq.denom()));
+                                freq_list::show_summary(ui, &self.sweep_freqs, &mut self.freq_summary);
+                            }
+                        }
+                    });
+
+                    // Targeted sweep around the enabled resonators in the resonator list
+                    ui.group(|ui| {
+                        ui.heading("Targeted Sweep");
+
+                        ui.horizontal(|ui| {
+                            ui.label("Span per Resonator (Hz):");
+                            ui.text_edit_singleline(&mut self.targeted_span);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Points per Resonator:");
+                            ui.text_edit_singleline(&mut self.targeted_points);
+                        });
+
+                        if ui.button("Generate From Resonator List").clicked() {
+                            match (self.targeted_span.parse::<f64>(), self.targeted_points.parse::<usize>()) {
+                                (Ok(span), Ok(points)) if points > 0 => {
+                                    let targeted = TargetedSweep::from_resonators(&self.resonator_list, span, points);
+                                    match targeted.freqs() {
+                                        Ok(freqs) => {
+                                            self.sweep_freqs = freqs;
+                                            self.targeted_sweep = Some(targeted);
+                                            self.error_message = None;
+                                        }
+                                        Err(e) => self.error_message = Some(e),
+                                    }
                                 }
+                                _ => self.error_message = Some("Invalid input: Enter a valid span and number of points.".to_string()),
                             }
                         }
+
+                        if let Some(ref targeted) = self.targeted_sweep {
+                            ui.label(format!("Frequency list targets {} resonators ({} points).", targeted.windows.len(), self.sweep_freqs.len()));
+                        }
                     });
 
                     // Power Settings
@@ -459,6 +830,9 @@ impl App for MyApp {
                             if ui.radio_value(&mut self.settings.if_atten_mode, "Board".to_string(), "Board").clicked() {
                                 self.settings.if_atten_mode = "Board".to_string();
                             }
+                            if ui.radio_value(&mut self.settings.if_atten_mode, "Table".to_string(), "Table").clicked() {
+                                self.settings.if_atten_mode = "Table".to_string();
+                            }
                         });
 
                         // Handle manual input or fetching attenuations from the board
@@ -485,6 +859,15 @@ impl App for MyApp {
                             } else {
                                 ui.label("Attenuation not available.");
                             }
+                        } else if self.settings.if_atten_mode == "Table" {
+                            // Each row expands to a range of output attenuations with its own FFT scale
+                            ui.label("Power sweep table (one sweep over every setting):");
+                            self.power_table.show(ui);
+                        }
+
+                        // The table carries its own FFT scale per row
+                        if self.settings.if_atten_mode == "Table" {
+                            return;
                         }
 
                         // Input for DSP scale
@@ -508,7 +891,7 @@ impl App for MyApp {
                                 self.command.send(RPCCommand::GetFFTScale).unwrap();
                             }
 
-                            ui.label(format!("Current DSP Scale: {}", self.settings.fft_scale));
+                            ui.label(format!("Current DSP Scale: {}", self.board_fft_scale.map_or("unknown".to_string(), |s| s.to_string())));
                         }
                     });
 
@@ -522,11 +905,29 @@ impl App for MyApp {
                         });
 
                         // Button to perform the sweep
-                        if ui.button("Perform Sweep").clicked() {
+                        let perform_sweep = ui.button("Perform Sweep").clicked();
+                        if perform_sweep && self.settings.if_atten_mode == "Table" {
+                            // Multi-power sweep built from the power table
+                            match (self.power_table.settings(), self.sweep_average.parse::<u64>()) {
+                                (Ok(settings), Ok(average)) => {
+                                    let config = SweepConfig {
+                                        freqs: self.sweep_freqs.clone(),
+                                        settings,
+                                        average,
+                                    };
+
+                                    self.command.send(RPCCommand::SweepConfig(config)).unwrap();
+                                    self.sweeps_in_flight += 1;
+                                    self.error_message = None;
+                                }
+                                (Err(e), _) => self.error_message = Some(e),
+                                (_, Err(_)) => self.error_message = Some("Invalid average value.".to_string()),
+                            }
+                        } else if perform_sweep {
                             let dsp_scale = if self.settings.dsp_scale_mode == "Manual" {
                                 self.sweep_dsp_scale.parse::<u16>().ok()
                             } else {
-                                self.settings.fft_scale.parse::<u16>().ok()
+                                self.board_fft_scale
                             };
 
                             let input_atten = if self.settings.if_atten_mode == "Manual" {
@@ -562,13 +963,13 @@ impl App for MyApp {
                                 };
 
                                 self.command.send(RPCCommand::SweepConfig(config)).unwrap();
+                                self.sweeps_in_flight += 1;
                             } else {
                                 self.error_message = Some("Invalid input values.".to_string());
                             }
                         }
 
                         // Button to perform a capture 
-                        // Non-functional (yields error)
                         if ui.button("Capture").clicked() {
                             self.command.send(RPCCommand::PerformCapture).unwrap();
                         }
@@ -584,11 +985,204 @@ impl App for MyApp {
                         ui.label(sweep_result);
                     }
                 }
+                Pane::PowerMap => {
+                    ui.heading("Power Sweep Map");
+                    self.power_map.show(ui);
+                }
+                Pane::ResonatorFinder => {
+                    ui.heading("Resonator Finder");
+                    self.resonator_finder.show(ui, &self.sweep_traces);
+                }
+                Pane::ResonatorFit => {
+                    ui.heading("Resonator Fitting");
+                    self.resonator_fitter.show(ui, &self.sweep_traces, &self.resonator_finder.found, &self.resonator_sweeps);
+                }
+                Pane::Resonators => {
+                    ui.heading("Resonators");
+                    self.resonator_list.show(ui, &self.resonator_finder.found, &self.resonator_fitter.fits, &self.power_map.picks);
+                }
+                Pane::Drift => {
+                    ui.heading("Drift Tracking");
+                    self.drift.show(ui, self.resonator_list.enabled().count());
+                }
+                Pane::IQLoops => {
+                    ui.heading("IQ Loop Calibration");
+                    self.loop_calibrator.show(ui, &mut self.resonator_list, &self.resonator_sweeps);
+                }
+                Pane::Timestream => {
+                    ui.heading("Timestream");
+                    if self.timestream.show(ui) {
+                        self.pulse_capture = false;
+                        self.send_timestream_capture();
+                    }
+                }
+                Pane::Pulses => {
+                    ui.heading("Pulse Detection");
+                    if self.pulse_detector.show(ui, self.timestream.in_flight) {
+                        self.pulse_capture = true;
+                        self.send_timestream_capture();
+                    }
+                }
+                Pane::Noise => {
+                    ui.heading("Noise");
+                    if self.noise.show(ui) {
+                        self.start_noise();
+                    }
+                }
+                Pane::OptimalFilter => {
+                    ui.heading("Optimal Filters");
+                    if self.filter_builder.show(ui, self.pulse_detector.pulses.len(), self.noise.psds.len()) {
+                        let result = self.filter_builder.build(&self.pulse_detector.pulses, &self.noise, self.timestream.sample_rate());
+                        self.filter_builder.message = Some(result.unwrap_or_else(|e| e));
+                    }
+                }
+                Pane::LiveCapture => {
+                    ui.heading("Live Capture");
+                    if let Some(command) = self.live_capture.show(ui) {
+                        self.command.send(command).unwrap();
+                    }
+                }
+                Pane::Waterfall => {
+                    ui.heading("Waterfall");
+                    self.waterfall.show(ui);
+                }
+                Pane::History => {
+                    ui.heading("Settings History");
+                    if let Some(command) = self.history.show(ui) {
+                        self.command.send(command).unwrap();
+                    }
+                }
             }
         });
     }
 }
 
+impl MyApp {
+    // Save the generated comb, or else the table last read from the board, to the library
+    fn save_dac_table(&mut self) {
+        let result = match (&self.dac_comb, &self.dac_table) {
+            (Some(comb), _) => self.dac_library.save(&comb.table, comb.tones.clone(), Some(comb.lo), Some(comb.fill)),
+            (None, Some(table)) => self.dac_library.save(table, Vec::new(), None, None),
+            (None, None) => Err("Generate a comb or get the DAC table first.".to_string()),
+        };
+        self.dac_library.message = Some(result.unwrap_or_else(|e| e));
+    }
+
+    // Record a capture's saturation against the current attenuation and DSP scale
+    fn record_saturation(&mut self, report: SaturationReport) {
+        let input_atten = self.if_attens.as_ref().map(|a| a.input);
+        let fft_scale = self.board_fft_scale;
+        self.saturation.record(report, input_atten, fft_scale);
+    }
+
+    // Start a noise measurement with the timestream pane's capture settings
+    fn start_noise(&mut self) {
+        let ((captures, segment), (channels, length)) = match (self.noise.settings(), self.timestream.settings()) {
+            (Ok(noise), Ok(capture)) => (noise, capture),
+            (Err(e), _) | (_, Err(e)) => {
+                self.noise.message = Some(e);
+                return;
+            }
+        };
+        // Channel i reads out the i-th tone of the loaded comb
+        let tones: Vec<f64> = self.dac_comb.as_ref().map_or_else(Vec::new, |comb| comb.tones.iter().map(|t| t.freq).collect());
+        self.noise.start(NoiseMetadata {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            tap: format!("{:?}", self.timestream.tap),
+            resonator_freqs: channels.iter().map(|c| tones.get(*c).copied()).collect(),
+            channels,
+            capture_length: length,
+            sample_rate: self.timestream.sample_rate(),
+            captures,
+            segment,
+            if_freq: self.if_freq.as_ref().map(sweep_data::hertz_to_f64),
+            input_atten: self.if_attens.as_ref().map(|a| a.input),
+            output_atten: self.if_attens.as_ref().map(|a| a.output),
+            fft_scale: self.board_fft_scale,
+        });
+    }
+
+    fn send_timestream_capture(&mut self) {
+        match self.timestream.command() {
+            Ok(command) => self.command.send(command).unwrap(),
+            Err(e) => {
+                self.timestream.message = Some(e);
+                self.timestream.live = false;
+                self.pulse_detector.live = false;
+                self.pulse_capture = false;
+            }
+        }
+    }
+
+    // Regenerate the comb at the fitted f0 values and upload it
+    fn retune(&mut self, drift_f0s: BTreeMap<u32, f64>) {
+        let f0s = match self.retuner.source {
+            F0Source::Fits => retune::fitted_f0s(&self.resonator_list, &self.resonator_fitter.fits),
+            F0Source::Drift => drift_f0s,
+        };
+        let (Some(lo), Ok(fill)) = (self.if_freq.as_ref(), self.dac_fill.parse::<f64>()) else {
+            self.retuner.message = Some("Get the IF frequency and enter a valid fill value first.".to_string());
+            return;
+        };
+
+        let result = self.retuner.retune(
+            &self.resonator_list,
+            &f0s,
+            self.dac_comb.as_ref(),
+            self.dac_table.as_ref(),
+            sweep_data::hertz_to_f64(lo),
+            fill,
+        );
+        self.retuner.message = Some(match result {
+            Ok(table) => match set_dac_table(&self.command, table) {
+                Ok(()) => "Uploading the retuned DAC table.".to_string(),
+                Err(e) => {
+                    self.retuner.failed(None);
+                    format!("Failed to set DAC table: {}", e)
+                }
+            },
+            Err(e) => e,
+        });
+    }
+
+    // Send a targeted sweep around every enabled resonator at the board's current power settings
+    fn send_drift_sweep(&mut self) {
+        let (span, points, average) = match self.drift.window_settings() {
+            Ok(settings) => settings,
+            Err(e) => {
+                self.drift.message = Some(e);
+                self.drift.running = false;
+                return;
+            }
+        };
+        let attens = self.if_attens.as_ref().map(|a| Attens { input: a.input, output: a.output });
+        let (Some(attens), Some(fft_scale)) = (attens, self.board_fft_scale) else {
+            self.drift.message = Some("Read the IF attenuations and DSP scale from the board before tracking.".to_string());
+            self.drift.running = false;
+            return;
+        };
+
+        let targeted = TargetedSweep::from_resonators(&self.resonator_list, span, points);
+        let freqs = match targeted.freqs() {
+            Ok(freqs) if !freqs.is_empty() => freqs,
+            result => {
+                self.drift.message = Some(result.err().unwrap_or_else(|| "No enabled resonators to track.".to_string()));
+                self.drift.running = false;
+                return;
+            }
+        };
+
+        let config = SweepConfig {
+            freqs,
+            settings: vec![PowerSetting { attens, fft_scale }],
+            average,
+        };
+        self.command.send(RPCCommand::SweepConfig(config)).unwrap();
+        self.sweeps_in_flight += 1;
+        self.drift.sent(targeted);
+    }
+}
+
 // Function to run a command and return the output
 fn run_command(command: &str) -> String {
     let output = Command::new("sh")
@@ -612,7 +1206,7 @@ fn set_scale(tx: &Sender<RPCCommand>, scale: u16) -> Result<(), Box<dyn std::err
 }
 
 // Function to set the DAC table
-fn set_dac_table(tx: &Sender<RPCCommand>, data: Box<[Complex<i16>; 524288]>) -> Result<(), Box<dyn std::error::Error>> {
+fn set_dac_table(tx: &Sender<RPCCommand>, data: DacTable) -> Result<(), Box<dyn std::error::Error>> {
     println!("Setting DAC table");
     tx.send(RPCCommand::SetDACTable(data))?;
     Ok(())
@@ -657,15 +1251,46 @@ pub fn run_gui(command: Sender<RPCCommand>, response: Receiver<RPCResponse>) {
                 dac_table: None,
                 if_freq: None,
                 if_attens: None,
+                board_fft_scale: None,
                 connection_time: None,
-                sweep_start_freq: String::new(),
-                sweep_stop_freq: String::new(),
-                sweep_count: String::new(),
+                freq_builder: FreqListBuilder::default(),
                 sweep_freqs: Vec::new(),
+                freq_summary: None,
                 sweep_input_atten: String::new(),
                 sweep_output_atten: String::new(),
                 sweep_dsp_scale: String::new(),
                 sweep_average: String::new(),
+                power_table: PowerTable::default(),
+                sweep_traces: Vec::new(),
+                power_map: PowerMap::default(),
+                resonator_finder: ResonatorFinder::default(),
+                resonator_fitter: ResonatorFitter::default(),
+                resonator_list: ResonatorList::default(),
+                dac_comb: None,
+                pending_comb: None,
+                dac_fill: "0.9".to_string(),
+                targeted_span: "500000".to_string(),
+                targeted_points: "101".to_string(),
+                targeted_sweep: None,
+                resonator_sweeps: Vec::new(),
+                drift: DriftTracker::default(),
+                sweeps_in_flight: 0,
+                retuner: Retuner::default(),
+                loop_calibrator: LoopCalibrator::default(),
+                timestream: Timestream::default(),
+                pulse_detector: PulseDetector::default(),
+                pulse_capture: false,
+                noise: NoiseMeasurement::default(),
+                filter_builder: FilterBuilder::default(),
+                live_capture: LiveCapturePane::default(),
+                waterfall: Waterfall::default(),
+                saturation: SaturationMonitor::default(),
+                auto_scale: AutoScale::default(),
+                shift_editor: ShiftEditor::default(),
+                board_state: BoardStateMonitor::default(),
+                verify_log: VerifyLog::default(),
+                dac_library: DacLibrary::default(),
+                history: History::default(),
                 sweep_result: None,
             }))
         }),
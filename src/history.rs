@@ -0,0 +1,162 @@
+// Board settings history
+// Lists every write the worker applied with the value it replaced, and undoes/redoes them by writing the other value
+// Called to in gui
+
+use crate::sweep_data;
+use crate::worker::{AppliedWrite, BoardWrite, RPCCommand};
+use eframe::egui;
+
+// Oldest entries are dropped beyond this
+const MAX_ENTRIES: usize = 200;
+
+// DAC table entries hold up to two 2 MB tables each, so far fewer of them are kept
+const MAX_TABLE_ENTRIES: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Undo,
+    Redo,
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<AppliedWrite>,           // Oldest first
+    cursor: usize,                        // entries[..cursor] are in effect, the rest were undone
+    pending: Option<(Step, BoardWrite)>, // Undo/redo sent to the worker and not yet applied
+    pub message: Option<String>,
+}
+
+fn setting(write: &BoardWrite) -> &'static str {
+    match write {
+        BoardWrite::FFTScale(_) => "FFT scale",
+        BoardWrite::DACTable(..) => "DAC table",
+        BoardWrite::IFFreq(_) => "IF frequency",
+        BoardWrite::IFAttens(..) => "IF attenuation",
+    }
+}
+
+fn value(write: &BoardWrite) -> String {
+    match write {
+        BoardWrite::FFTScale(s) => s.to_string(),
+        BoardWrite::DACTable(_, hash) => format!("table {:016x}", hash),
+        BoardWrite::IFFreq(f) => format!("{:.6} MHz", sweep_data::hertz_to_f64(f) / 1e6),
+        BoardWrite::IFAttens(input, output) => format!("{}/{} dB", input, output),
+    }
+}
+
+impl History {
+    // Log a write from the worker; undo/redo writes move the cursor instead of adding an entry.
+    // Any other write (including a clamped or rounded undo/redo) ends the pending undo/redo and is logged.
+    pub fn record(&mut self, write: AppliedWrite) {
+        if let Some((step, target)) = self.pending.take() {
+            if write.after.same(&target) {
+                match step {
+                    Step::Undo => self.cursor = self.cursor.saturating_sub(1),
+                    Step::Redo => self.cursor = (self.cursor + 1).min(self.entries.len()),
+                }
+                return;
+            }
+        }
+        // Writes that changed nothing (e.g. re-sending the loaded DAC table) are not worth an entry
+        if write.before.as_ref().is_some_and(|b| b.same(&write.after)) {
+            return;
+        }
+
+        // A new write discards the undone entries
+        self.entries.truncate(self.cursor);
+        self.entries.push(write);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        let is_table = |e: &AppliedWrite| matches!(e.after, BoardWrite::DACTable(..));
+        if self.entries.iter().filter(|e| is_table(e)).count() > MAX_TABLE_ENTRIES {
+            if let Some(oldest) = self.entries.iter().position(is_table) {
+                self.entries.remove(oldest);
+            }
+        }
+        self.cursor = self.entries.len();
+    }
+
+    // A set command failed; if it was the pending undo/redo, that did not happen
+    pub fn failed(&mut self, write: &BoardWrite) {
+        if self.pending.as_ref().is_some_and(|(_, target)| target.same(write)) {
+            self.pending = None;
+            self.message = Some("Undo/redo failed: the board did not take the value.".to_string());
+        }
+    }
+
+    // Command restoring the value before the newest write in effect
+    fn undo(&mut self) -> Option<RPCCommand> {
+        let entry = self.entries[..self.cursor].last()?;
+        let Some(before) = entry.before.clone() else {
+            self.message = Some(format!("Can't undo: the {} before this write could not be read.", setting(&entry.after)));
+            return None;
+        };
+        self.message = Some(format!("Undo: {} back to {}", setting(&before), value(&before)));
+        let command = before.command();
+        self.pending = Some((Step::Undo, before));
+        Some(command)
+    }
+
+    // Command re-applying the oldest undone write
+    fn redo(&mut self) -> Option<RPCCommand> {
+        let after = self.entries.get(self.cursor)?.after.clone();
+        self.message = Some(format!("Redo: {} to {}", setting(&after), value(&after)));
+        let command = after.command();
+        self.pending = Some((Step::Redo, after));
+        Some(command)
+    }
+
+    // Draw the history pane; returns an undo/redo command for the worker
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<RPCCommand> {
+        let mut command = None;
+
+        ui.horizontal(|ui| {
+            // One undo/redo at a time, the cursor only moves once the worker applies it
+            let idle = self.pending.is_none();
+            let undo = self.entries[..self.cursor].last().map(|e| format!("Undo {}", setting(&e.after)));
+            if ui.add_enabled(idle && undo.is_some(), egui::Button::new(undo.unwrap_or("Undo".to_string()))).clicked() {
+                command = self.undo();
+            }
+            let redo = self.entries.get(self.cursor).map(|e| format!("Redo {}", setting(&e.after)));
+            if ui.add_enabled(idle && redo.is_some(), egui::Button::new(redo.unwrap_or("Redo".to_string()))).clicked() {
+                command = self.redo();
+            }
+            if ui.button("Clear").clicked() {
+                self.entries.clear();
+                self.cursor = 0;
+                self.pending = None;
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+        if self.entries.is_empty() {
+            ui.label("No settings written yet.");
+            return command;
+        }
+
+        egui::ScrollArea::vertical().id_salt("history").show(ui, |ui| {
+            egui::Grid::new("history_grid").striped(true).show(ui, |ui| {
+                ui.label("When");
+                ui.label("Setting");
+                ui.label("Before");
+                ui.label("After");
+                ui.label("");
+                ui.end_row();
+                for (i, entry) in self.entries.iter().enumerate().rev() {
+                    let age = entry.time.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+                    ui.label(format!("{} s ago", age));
+                    ui.label(setting(&entry.after));
+                    ui.label(entry.before.as_ref().map_or("unknown".to_string(), value));
+                    ui.label(value(&entry.after));
+                    ui.label(if i < self.cursor { "applied" } else { "undone" });
+                    ui.end_row();
+                }
+            });
+        });
+
+        command
+    }
+}
@@ -0,0 +1,214 @@
+// IQ loop calibration
+// Finds the centre and rotation of each resonator's IQ loop from a sweep so captured IQ can be turned into phase and dissipation
+// Called to in gui
+
+use crate::resonator_fit::solve;
+use crate::resonators::ResonatorList;
+use crate::sweep_data::SweepTrace;
+use crate::targeted_sweep::ResonatorSweep;
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, Points};
+use num::Complex;
+use serde::{Deserialize, Serialize};
+
+// Translation and rotation that put a resonator's IQ loop at the origin with the resonance point on the +I axis
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct LoopCal {
+    pub center_i: f64, // Loop centre in raw IQ
+    pub center_q: f64,
+    pub radius: f64,   // Loop radius in raw IQ
+    pub rotation: f64, // Angle of the resonance point about the centre (rad)
+}
+
+impl LoopCal {
+    pub fn center(&self) -> Complex<f64> {
+        Complex::new(self.center_i, self.center_q)
+    }
+
+    // Calibrated IQ: translated to the origin, rotated and normalised to the loop radius
+    pub fn transform(&self, z: Complex<f64>) -> Complex<f64> {
+        (z - self.center()) * Complex::from_polar(1.0 / self.radius, -self.rotation)
+    }
+
+    // Phase (rad) and dissipation (fractional change of the loop radius) timestreams of captured IQ
+    pub fn apply(&self, iq: &[Complex<f64>]) -> (Vec<f64>, Vec<f64>) {
+        iq.iter()
+            .map(|&z| {
+                let z = self.transform(z);
+                (z.arg(), 1.0 - z.norm())
+            })
+            .unzip()
+    }
+}
+
+// Algebraic (Kasa) circle fit: minimise sum (x^2 + y^2 + D x + E y + F)^2
+fn fit_circle(iq: &[Complex<f64>]) -> Option<(Complex<f64>, f64)> {
+    // Normal equations, centred on the mean for conditioning
+    let n = iq.len() as f64;
+    let mean = iq.iter().sum::<Complex<f64>>() / n;
+    let mut m = [[0.0; 3]; 3];
+    let mut v = [0.0; 3];
+    for z in iq {
+        let p = z - mean;
+        let row = [p.re, p.im, 1.0];
+        let rhs = -p.norm_sqr();
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += row[i] * row[j];
+            }
+            v[i] += row[i] * rhs;
+        }
+    }
+
+    let x = solve(m, v)?;
+
+    let center = Complex::new(-x[0] / 2.0, -x[1] / 2.0);
+    let r2 = center.norm_sqr() - x[2];
+    (r2 > 0.0).then(|| (center + mean, r2.sqrt()))
+}
+
+// Calibrate one resonator from the sweep window around it; the resonance point is where the IQ velocity peaks
+pub fn calibrate(trace: &SweepTrace) -> Result<LoopCal, String> {
+    if trace.iq.len() < 5 {
+        return Err("Too few sweep points to calibrate the IQ loop.".to_string());
+    }
+    let (center, radius) = fit_circle(&trace.iq).ok_or_else(|| "IQ loop circle fit failed.".to_string())?;
+    let resonance = trace
+        .iq_velocity()
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| trace.iq[i])
+        .unwrap();
+    Ok(LoopCal {
+        center_i: center.re,
+        center_q: center.im,
+        radius,
+        rotation: (resonance - center).arg(),
+    })
+}
+
+// IQ loop calibration pane state
+#[derive(Default)]
+pub struct LoopCalibrator {
+    pub trace_index: usize,      // Power setting of the targeted sweep to calibrate from
+    pub selected: Option<u32>,   // Resonator shown in the plots
+    pub message: Option<String>, // Error or status message
+}
+
+impl LoopCalibrator {
+    // Calibrate every resonator of the last targeted sweep and store the result in the resonator list
+    pub fn calibrate_all(&mut self, list: &mut ResonatorList, sweeps: &[ResonatorSweep]) {
+        let mut calibrated = 0;
+        let mut failed = 0;
+        for sweep in sweeps {
+            let (Some(id), Some(trace)) = (sweep.id, sweep.traces.get(self.trace_index)) else {
+                continue;
+            };
+            let Some(r) = list.resonators.iter_mut().find(|r| r.id == id) else {
+                continue;
+            };
+            match calibrate(trace) {
+                Ok(cal) => {
+                    r.loop_cal = Some(cal);
+                    calibrated += 1;
+                }
+                Err(_) => failed += 1,
+            }
+        }
+        self.message = Some(format!("Calibrated {} IQ loops, {} failed.", calibrated, failed));
+    }
+
+    // Draw the calibration pane
+    pub fn show(&mut self, ui: &mut egui::Ui, list: &mut ResonatorList, sweeps: &[ResonatorSweep]) {
+        let settings = sweeps.first().map_or(0, |s| s.traces.len());
+        ui.label(format!("Last targeted sweep: {} resonators, {} power settings", sweeps.len(), settings));
+        ui.horizontal(|ui| {
+            ui.label("Power Setting:");
+            ui.add(egui::DragValue::new(&mut self.trace_index).range(0..=settings.saturating_sub(1)));
+            if ui.button("Calibrate From Targeted Sweep").clicked() {
+                if sweeps.iter().any(|s| s.id.is_some()) {
+                    self.calibrate_all(list, sweeps);
+                } else {
+                    self.message = Some("Perform a targeted sweep of the resonator list first.".to_string());
+                }
+            }
+            if ui.button("Clear Calibrations").clicked() {
+                list.resonators.iter_mut().for_each(|r| r.loop_cal = None);
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Calibration table
+        egui::ScrollArea::vertical().id_salt("loop_cal_table").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("loop_cal_grid").striped(true).show(ui, |ui| {
+                for heading in ["ID", "Frequency (Hz)", "Centre I", "Centre Q", "Radius", "Rotation (rad)"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                for r in &list.resonators {
+                    if ui.selectable_label(self.selected == Some(r.id), r.id.to_string()).clicked() {
+                        self.selected = Some(r.id);
+                    }
+                    ui.label(format!("{:.1}", r.freq));
+                    if let Some(cal) = r.loop_cal {
+                        ui.label(format!("{:.4e}", cal.center_i));
+                        ui.label(format!("{:.4e}", cal.center_q));
+                        ui.label(format!("{:.4e}", cal.radius));
+                        ui.label(format!("{:.4}", cal.rotation));
+                    } else {
+                        ui.label("-");
+                        ui.label("-");
+                        ui.label("-");
+                        ui.label("-");
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        // Calibrated loop and phase/dissipation of the selected resonator's sweep
+        let resonator = self.selected.and_then(|id| list.resonators.iter().find(|r| r.id == id));
+        let trace = self
+            .selected
+            .and_then(|id| sweeps.iter().find(|s| s.id == Some(id)))
+            .and_then(|s| s.traces.get(self.trace_index));
+        let (Some(cal), Some(trace)) = (resonator.and_then(|r| r.loop_cal), trace) else {
+            ui.label("Select a calibrated resonator of the last targeted sweep to preview its loop.");
+            return;
+        };
+
+        let calibrated: Vec<[f64; 2]> = trace.iq.iter().map(|&z| cal.transform(z)).map(|z| [z.re, z.im]).collect();
+        let unit_circle: Vec<[f64; 2]> = (0..=100)
+            .map(|i| Complex::from_polar(1.0, i as f64 * std::f64::consts::TAU / 100.0))
+            .map(|z: Complex<f64>| [z.re, z.im])
+            .collect();
+        let (phase, dissipation) = cal.apply(&trace.iq);
+
+        ui.columns(2, |columns| {
+            Plot::new("loop_cal_iq")
+                .height(300.0)
+                .data_aspect(1.0)
+                .legend(Legend::default())
+                .x_axis_label("I (calibrated)")
+                .y_axis_label("Q (calibrated)")
+                .show(&mut columns[0], |plot_ui| {
+                    plot_ui.line(Line::new(unit_circle).name("Unit circle"));
+                    plot_ui.points(Points::new(calibrated).radius(2.0).name("Sweep"));
+                });
+            Plot::new("loop_cal_timestreams")
+                .height(300.0)
+                .legend(Legend::default())
+                .x_axis_label("Frequency (Hz)")
+                .show(&mut columns[1], |plot_ui| {
+                    let line = |values: &[f64]| -> Vec<[f64; 2]> { trace.freqs.iter().zip(values).map(|(f, v)| [*f, *v]).collect() };
+                    plot_ui.line(Line::new(line(&phase)).name("Phase (rad)"));
+                    plot_ui.line(Line::new(line(&dissipation)).name("Dissipation"));
+                });
+        });
+    }
+}
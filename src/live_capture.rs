@@ -0,0 +1,196 @@
+// Live raw IQ capture
+// Starts/stops repeated captures in the worker and redraws IQ and spectrum plots at a limited frame rate
+// Called to in gui
+
+use crate::dac_comb::DAC_SAMPLE_RATE;
+use crate::worker::{LiveFrame, RPCCommand};
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, Points};
+use num::Complex;
+use rustfft::FftPlanner;
+use std::time::{Duration, Instant};
+
+// Most IQ points drawn in the constellation plot
+const MAX_IQ_POINTS: usize = 4096;
+
+// Power spectrum (dB) of raw IQ, negative frequencies first
+pub fn spectrum_db(data: &[Complex<i16>]) -> Vec<f64> {
+    let n = data.len();
+    let mut buffer: Vec<Complex<f64>> = data.iter().map(|z| Complex::new(z.re as f64, z.im as f64)).collect();
+    FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+    buffer.rotate_left(n / 2);
+    buffer.iter().map(|z| 10.0 * (z.norm_sqr() / (n as f64 * n as f64)).max(f64::MIN_POSITIVE).log10()).collect()
+}
+
+// Frequency offset (MHz) of each spectrum_db bin
+pub fn spectrum_freqs(n: usize) -> Vec<f64> {
+    (0..n).map(|k| (k as f64 - (n / 2) as f64) * DAC_SAMPLE_RATE / n as f64 / 1e6).collect()
+}
+
+pub struct LiveCapturePane {
+    pub rate: String,    // Captures per second requested from the worker
+    pub length: String,  // Samples per capture
+    pub max_fps: String, // Plot redraw limit
+    pub running: bool,
+    pub message: Option<String>,
+    pending: Option<LiveFrame>,          // Newest frame not yet drawn
+    iq: Vec<[f64; 2]>,                   // Drawn frame
+    spectrum: Vec<[f64; 2]>,
+    received: u64,
+    drawn: u64,
+    superseded: u64,                     // Frames replaced by a newer one before they were drawn
+    last_frame: Option<(u64, u64, u64)>, // Sequence, missed and failed of the newest frame
+    last_draw: Option<Instant>,
+}
+
+impl Default for LiveCapturePane {
+    fn default() -> Self {
+        Self {
+            rate: "10".to_string(),
+            length: "4096".to_string(),
+            max_fps: "20".to_string(),
+            running: false,
+            message: None,
+            pending: None,
+            iq: Vec::new(),
+            spectrum: Vec::new(),
+            received: 0,
+            drawn: 0,
+            superseded: 0,
+            last_frame: None,
+            last_draw: None,
+        }
+    }
+}
+
+impl LiveCapturePane {
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.max_fps.trim().parse::<f64>().unwrap_or(20.0).clamp(1.0, 240.0))
+    }
+
+    // Start command for the worker
+    fn start(&mut self) -> Result<RPCCommand, String> {
+        let (Ok(rate), Ok(length)) = (self.rate.trim().parse::<f64>(), self.length.trim().parse::<u64>()) else {
+            return Err("Invalid capture rate or length.".to_string());
+        };
+        if rate <= 0.0 || length == 0 {
+            return Err("Capture rate and length must be positive.".to_string());
+        }
+        self.running = true;
+        self.received = 0;
+        self.drawn = 0;
+        self.superseded = 0;
+        Ok(RPCCommand::StartLiveCapture(Duration::from_secs_f64(1.0 / rate), length))
+    }
+
+    // Queue a frame from the worker; only the newest undrawn frame is kept
+    pub fn receive(&mut self, frame: LiveFrame) {
+        self.received += 1;
+        self.last_frame = Some((frame.sequence, frame.missed, frame.failed));
+        if self.pending.replace(frame).is_some() {
+            self.superseded += 1;
+        }
+    }
+
+    // Show a one-shot capture
+    pub fn show_capture(&mut self, data: Vec<Complex<i16>>) {
+        if self.pending.replace(LiveFrame { sequence: 0, missed: 0, failed: 0, data }).is_some() {
+            self.superseded += 1;
+        }
+    }
+
+    // Draw the pending frame if the frame-rate limit allows, else ask for a repaint when it does
+    pub fn update(&mut self, ctx: &egui::Context) {
+        let interval = self.frame_interval();
+        if self.running {
+            // Keep polling for frames from the worker
+            ctx.request_repaint_after(interval);
+        }
+        if self.pending.is_none() {
+            return;
+        }
+        if let Some(wait) = self.last_draw.and_then(|t| interval.checked_sub(t.elapsed())) {
+            ctx.request_repaint_after(wait);
+            return;
+        }
+        let Some(frame) = self.pending.take() else {
+            return;
+        };
+        self.last_draw = Some(Instant::now());
+        if frame.data.is_empty() {
+            return;
+        }
+        self.drawn += 1;
+
+        let step = frame.data.len().div_ceil(MAX_IQ_POINTS);
+        self.iq = frame.data.iter().step_by(step).map(|z| [z.re as f64, z.im as f64]).collect();
+        let spectrum = spectrum_db(&frame.data);
+        self.spectrum = spectrum_freqs(spectrum.len()).into_iter().zip(&spectrum).map(|(f, p)| [f, *p]).collect();
+    }
+
+    // Draw the live capture pane; returns a command for the worker when started or stopped
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<RPCCommand> {
+        let mut command = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Capture Rate (Hz):");
+            ui.text_edit_singleline(&mut self.rate);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Samples per Capture:");
+            ui.text_edit_singleline(&mut self.length);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Max Redraw Rate (fps):");
+            ui.text_edit_singleline(&mut self.max_fps);
+        });
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button("Stop").clicked() {
+                    self.running = false;
+                    command = Some(RPCCommand::StopLiveCapture);
+                }
+            } else if ui.button("Start").clicked() {
+                match self.start() {
+                    Ok(c) => {
+                        command = Some(c);
+                        self.message = None;
+                    }
+                    Err(e) => self.message = Some(e),
+                }
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Frame counters: missed and failed are counted by the worker, superseded by the redraw limit
+        let (sequence, missed, failed) = self.last_frame.unwrap_or_default();
+        ui.label(format!(
+            "Captures: {}  Received: {}  Drawn: {}  Dropped (not drawn): {}  Missed slots: {}  Failed: {}",
+            sequence, self.received, self.drawn, self.superseded, missed, failed
+        ));
+
+        ui.columns(2, |columns| {
+            Plot::new("live_iq")
+                .height(350.0)
+                .data_aspect(1.0)
+                .x_axis_label("I")
+                .y_axis_label("Q")
+                .show(&mut columns[0], |plot_ui| {
+                    plot_ui.points(Points::new(self.iq.clone()).radius(1.0));
+                });
+            Plot::new("live_spectrum")
+                .height(350.0)
+                .legend(Legend::default())
+                .x_axis_label("Frequency Offset (MHz)")
+                .y_axis_label("Power (dB)")
+                .show(&mut columns[1], |plot_ui| {
+                    plot_ui.line(Line::new(self.spectrum.clone()).name("Spectrum"));
+                });
+        });
+
+        command
+    }
+}
@@ -1,6 +1,31 @@
+mod auto_scale;
+mod board_state;
+mod dac_comb;
+mod dac_library;
+mod drift;
+mod fft_shift;
+mod freq_list;
 mod gui;
+mod history;
+mod iq_loop;
+mod live_capture;
 mod logger;
+mod noise;
+mod optimal_filter;
+mod power_map;
+mod power_sweep;
+mod pulses;
+mod resonator_finder;
+mod resonator_fit;
+mod resonators;
+mod retune;
+mod saturation;
 mod status;
+mod sweep_data;
+mod targeted_sweep;
+mod timestream;
+mod verify;
+mod waterfall;
 mod worker;
 
 use std::sync::mpsc::channel;
@@ -0,0 +1,271 @@
+// Noise power spectral densities
+// Takes N timestream captures and averages Welch PSDs of phase and dissipation per channel, plotted log-log and saved with metadata
+// Called to in gui
+
+use crate::timestream::ChannelSamples;
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot};
+use num::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::path::Path;
+
+// Board and capture settings at the start of a noise measurement
+#[derive(Serialize, Clone)]
+pub struct NoiseMetadata {
+    pub time: u64,                         // Unix time (s)
+    pub tap: String,                       // Capture tap
+    pub channels: Vec<usize>,
+    pub resonator_freqs: Vec<Option<f64>>, // Tone frequency of each channel (Hz)
+    pub capture_length: u64,               // Samples per capture
+    pub sample_rate: f64,                  // Channel sample rate (Hz)
+    pub captures: usize,                   // Captures averaged
+    pub segment: usize,                    // Welch segment length
+    pub if_freq: Option<f64>,              // IF LO frequency (Hz)
+    pub input_atten: Option<f32>,          // dB
+    pub output_atten: Option<f32>,         // dB
+    pub fft_scale: Option<u16>,
+}
+
+// Averaged PSDs of one channel (rad^2/Hz for phase, 1/Hz for dissipation)
+#[derive(Default)]
+pub struct ChannelPsd {
+    pub phase: Vec<f64>,
+    pub dissipation: Option<Vec<f64>>,
+    pub averages: usize, // Welch segments averaged
+}
+
+pub struct NoiseMeasurement {
+    pub captures: String,  // Number of captures to average
+    pub segment: String,   // Welch segment length (samples)
+    pub save_path: String, // npy file for the PSDs
+    pub running: bool,
+    pub remaining: usize,  // Captures still to take
+    pub metadata: Option<NoiseMetadata>,
+    pub psds: BTreeMap<usize, ChannelPsd>,
+    pub message: Option<String>,
+}
+
+impl Default for NoiseMeasurement {
+    fn default() -> Self {
+        Self {
+            captures: "10".to_string(),
+            segment: "1024".to_string(),
+            save_path: "noise.npy".to_string(),
+            running: false,
+            remaining: 0,
+            metadata: None,
+            psds: BTreeMap::new(),
+            message: None,
+        }
+    }
+}
+
+// Summed one-sided periodograms of Hann-windowed segments with 50% overlap, and the number of segments
+fn welch_sum(x: &[f64], segment: usize, sample_rate: f64) -> (Vec<f64>, usize) {
+    let window: Vec<f64> = (0..segment).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / segment as f64).cos()).collect();
+    let norm = sample_rate * window.iter().map(|w| w * w).sum::<f64>();
+    let fft = FftPlanner::new().plan_fft_forward(segment);
+    let bins = segment / 2 + 1;
+
+    let mut sum = vec![0.0; bins];
+    let mut count = 0;
+    let mut start = 0;
+    while start + segment <= x.len() {
+        let chunk = &x[start..start + segment];
+        let mean = chunk.iter().sum::<f64>() / segment as f64;
+        let mut buffer: Vec<Complex<f64>> = chunk.iter().zip(&window).map(|(v, w)| Complex::new((v - mean) * w, 0.0)).collect();
+        fft.process(&mut buffer);
+        for (k, s) in sum.iter_mut().enumerate() {
+            // Double every bin except DC and Nyquist for the one-sided PSD
+            let one_sided = if k == 0 || 2 * k == segment { 1.0 } else { 2.0 };
+            *s += one_sided * buffer[k].norm_sqr() / norm;
+        }
+        count += 1;
+        start += segment / 2;
+    }
+    (sum, count)
+}
+
+impl NoiseMeasurement {
+    fn segment(&self) -> Option<usize> {
+        self.segment.trim().parse::<usize>().ok().filter(|s| *s >= 2)
+    }
+
+    // Frequency axis of the PSDs (Hz); bin k of a segment of n samples is at k * rate / n, for odd n too
+    pub fn freqs(&self) -> Vec<f64> {
+        let (Some(metadata), Some(bins)) = (&self.metadata, self.psds.values().next().map(|p| p.phase.len())) else {
+            return Vec::new();
+        };
+        (0..bins).map(|k| k as f64 * metadata.sample_rate / metadata.segment as f64).collect()
+    }
+
+    // Number of captures and segment length, checked before the measurement starts
+    pub fn settings(&self) -> Result<(usize, usize), String> {
+        match (self.captures.trim().parse::<usize>(), self.segment()) {
+            (Ok(captures), Some(segment)) if captures > 0 => Ok((captures, segment)),
+            _ => Err("Invalid number of captures or segment length.".to_string()),
+        }
+    }
+
+    // Start a new measurement
+    pub fn start(&mut self, metadata: NoiseMetadata) {
+        self.remaining = metadata.captures;
+        self.metadata = Some(metadata);
+        self.psds.clear();
+        self.running = true;
+        self.message = None;
+    }
+
+    // Add the PSDs of one capture to the running averages
+    pub fn process(&mut self, latest: &[ChannelSamples]) {
+        // The segment the measurement started with, not whatever is typed in now
+        let Some(metadata) = &self.metadata else {
+            return;
+        };
+        let (segment, rate) = (metadata.segment, metadata.sample_rate);
+        for samples in latest {
+            let (phase, count) = welch_sum(&samples.phase, segment, rate);
+            if count == 0 {
+                self.message = Some("Captures are shorter than the Welch segment.".to_string());
+                self.running = false;
+                return;
+            }
+            let psd = self.psds.entry(samples.channel).or_default();
+            if psd.phase.len() != phase.len() {
+                *psd = ChannelPsd {
+                    phase: vec![0.0; phase.len()],
+                    dissipation: samples.dissipation.as_ref().map(|_| vec![0.0; phase.len()]),
+                    averages: 0,
+                };
+            }
+            psd.phase.iter_mut().zip(&phase).for_each(|(a, p)| *a += p);
+            if let (Some(sum), Some(dissipation)) = (psd.dissipation.as_mut(), samples.dissipation.as_ref()) {
+                let (d, _) = welch_sum(dissipation, segment, rate);
+                sum.iter_mut().zip(&d).for_each(|(a, p)| *a += p);
+            }
+            psd.averages += count;
+        }
+
+        self.remaining = self.remaining.saturating_sub(1);
+        if self.remaining == 0 {
+            self.running = false;
+            self.message = Some("Noise measurement complete.".to_string());
+        }
+    }
+
+    // Capture failed: abort the measurement
+    pub fn abort(&mut self) {
+        self.running = false;
+        self.message = Some("Noise measurement aborted: capture failed.".to_string());
+    }
+
+    // Averaged PSD of a channel: (phase, dissipation)
+    fn averaged(psd: &ChannelPsd) -> (Vec<f64>, Option<Vec<f64>>) {
+        let n = psd.averages.max(1) as f64;
+        (
+            psd.phase.iter().map(|p| p / n).collect(),
+            psd.dissipation.as_ref().map(|d| d.iter().map(|p| p / n).collect()),
+        )
+    }
+
+    // Averaged phase PSD of a channel with its frequency axis
+    pub fn phase_psd(&self, channel: usize) -> Option<(Vec<f64>, Vec<f64>)> {
+        let psd = self.psds.get(&channel)?;
+        Some((self.freqs(), Self::averaged(psd).0))
+    }
+
+    // Write the PSDs as rows [freqs, phase ch0, dissipation ch0, phase ch1, ...] (NaN where there is no
+    // dissipation) and the metadata as JSON next to it. Rows are in ascending channel order and the saved
+    // channels and resonator_freqs are listed in that same order, one entry per row pair.
+    pub fn save(&self, path: &Path) -> Result<String, String> {
+        let measured = self.metadata.as_ref().ok_or_else(|| "No noise measurement to save.".to_string())?;
+        let mut metadata = measured.clone();
+        metadata.channels = self.psds.keys().copied().collect();
+        metadata.resonator_freqs = metadata
+            .channels
+            .iter()
+            .map(|c| measured.channels.iter().position(|m| m == c).and_then(|i| measured.resonator_freqs[i]))
+            .collect();
+        let freqs = self.freqs();
+        let mut rows = vec![freqs.clone()];
+        for psd in self.psds.values() {
+            let (phase, dissipation) = Self::averaged(psd);
+            rows.push(phase);
+            rows.push(dissipation.unwrap_or_else(|| vec![f64::NAN; freqs.len()]));
+        }
+        let array = ndarray::Array2::from_shape_fn((rows.len(), freqs.len()), |(i, j)| rows[i][j]);
+        let meta_path = path.with_file_name(format!("{}_meta.json", path.file_stem().unwrap_or_default().to_string_lossy()));
+        ndarray_npy::write_npy(path, &array).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        serde_json::to_string_pretty(&metadata)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&meta_path, json))
+            .map_err(|e| format!("Failed to write {}: {}", meta_path.display(), e))?;
+        Ok(format!("Saved noise PSDs to {} and {}", path.display(), meta_path.display()))
+    }
+
+    // Draw the noise pane; returns true when a measurement was requested
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut start = false;
+
+        ui.label("Captures use the channels, length and sample rate of the Timestream pane.");
+        ui.horizontal(|ui| {
+            ui.label("Captures:");
+            ui.text_edit_singleline(&mut self.captures);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Welch Segment (samples):");
+            ui.text_edit_singleline(&mut self.segment);
+        });
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button("Stop").clicked() {
+                    self.running = false;
+                }
+                ui.label(format!("{} captures remaining", self.remaining));
+            } else if ui.button("Measure Noise").clicked() {
+                start = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Save (.npy):");
+            ui.text_edit_singleline(&mut self.save_path);
+            if ui.button("Save").clicked() {
+                self.message = Some(self.save(Path::new(self.save_path.trim())).unwrap_or_else(|e| e));
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Log-log PSDs: log10 of frequency against dB
+        let freqs = self.freqs();
+        Plot::new("noise_psd_plot")
+            .legend(Legend::default())
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("PSD (dB rad^2/Hz)")
+            .x_axis_formatter(|mark, _range| format!("{:.0}", 10f64.powf(mark.value)))
+            .show(ui, |plot_ui| {
+                let line = |psd: &[f64]| -> Vec<[f64; 2]> {
+                    freqs
+                        .iter()
+                        .zip(psd)
+                        .skip(1) // DC has no place on a log axis
+                        .map(|(f, p)| [f.log10(), 10.0 * p.max(f64::MIN_POSITIVE).log10()])
+                        .collect()
+                };
+                for (channel, psd) in &self.psds {
+                    let (phase, dissipation) = Self::averaged(psd);
+                    plot_ui.line(Line::new(line(&phase)).name(format!("Channel {} phase", channel)));
+                    if let Some(dissipation) = dissipation {
+                        plot_ui.line(Line::new(line(&dissipation)).name(format!("Channel {} dissipation", channel)));
+                    }
+                }
+            });
+
+        start
+    }
+}
@@ -0,0 +1,249 @@
+// Optimal filters
+// Averages detected pulses into per-channel templates, weights them by the measured phase noise PSD and exports FIR coefficients
+// Called to in gui
+
+use crate::noise::NoiseMeasurement;
+use crate::pulses::PulseRecord;
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot};
+use num::Complex;
+use rustfft::FftPlanner;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Template and filter of one channel
+pub struct ChannelFilter {
+    pub template: Vec<f64>,     // Averaged pulse, peak normalised to 1
+    pub coefficients: Vec<f64>, // FIR taps, newest sample first; filtering the template gives 1 at its peak
+    pub pulses: usize,          // Pulses averaged into the template
+    pub white: bool,            // No noise PSD for the channel, white noise assumed
+}
+
+// Sidecar description of an exported filter bank
+#[derive(Serialize)]
+struct FilterMetadata {
+    channels: Vec<usize>,
+    taps: usize,
+    sample_rate: f64,
+    pre_trigger: usize,
+    q15_scale: Vec<f64>, // Float coefficient = int16 coefficient * scale / 32767, per channel
+    white_noise: Vec<bool>,
+}
+
+pub struct FilterBuilder {
+    pub taps: String,        // Number of FIR taps
+    pub export_path: String, // npy file for the coefficients
+    pub filters: BTreeMap<usize, ChannelFilter>,
+    pub selected: Option<usize>,
+    pub message: Option<String>,
+    pre_trigger: usize, // Pre-trigger samples of the records the filters were built from
+    sample_rate: f64,
+}
+
+impl Default for FilterBuilder {
+    fn default() -> Self {
+        Self {
+            taps: "64".to_string(),
+            export_path: "filters.npy".to_string(),
+            filters: BTreeMap::new(),
+            selected: None,
+            message: None,
+            pre_trigger: 0,
+            sample_rate: 1.0,
+        }
+    }
+}
+
+// Average of a channel's pulse records normalised to a peak of 1
+fn template(records: &[&PulseRecord]) -> Option<Vec<f64>> {
+    let length = records.first()?.samples.len();
+    let records: Vec<_> = records.iter().filter(|r| r.samples.len() == length).collect();
+    let mut sum = vec![0.0; length];
+    for r in &records {
+        sum.iter_mut().zip(&r.samples).for_each(|(s, v)| *s += v);
+    }
+    let peak = sum.iter().copied().fold(0.0, f64::max);
+    (peak > 0.0).then(|| sum.iter().map(|s| s / peak).collect())
+}
+
+// Linear interpolation of a PSD onto a frequency, clamped to its ends
+fn interpolate(freqs: &[f64], psd: &[f64], f: f64) -> f64 {
+    let i = freqs.partition_point(|x| *x < f);
+    if i == 0 {
+        return psd[0];
+    }
+    if i >= freqs.len() {
+        return psd[psd.len() - 1];
+    }
+    let t = (f - freqs[i - 1]) / (freqs[i] - freqs[i - 1]);
+    psd[i - 1] + t * (psd[i] - psd[i - 1])
+}
+
+// Frequency domain optimal filter S(f) / J(f) back in the time domain, aligned with the template.
+// Without a noise PSD every bin is weighted equally (matched filter).
+fn optimal_filter(template: &[f64], noise: Option<(&[f64], &[f64])>, sample_rate: f64) -> Vec<f64> {
+    let n = template.len();
+    let mut planner = FftPlanner::new();
+    let mut spectrum: Vec<Complex<f64>> = template.iter().map(|v| Complex::new(*v, 0.0)).collect();
+    planner.plan_fft_forward(n).process(&mut spectrum);
+
+    for (k, s) in spectrum.iter_mut().enumerate() {
+        // Mean level carries no pulse information and is dominated by drifts
+        if k == 0 {
+            *s = Complex::new(0.0, 0.0);
+            continue;
+        }
+        if let Some((freqs, psd)) = noise {
+            let f = k.min(n - k) as f64 * sample_rate / n as f64;
+            *s /= interpolate(freqs, psd, f).max(f64::MIN_POSITIVE);
+        }
+    }
+    planner.plan_fft_inverse(n).process(&mut spectrum);
+    spectrum.iter().map(|z| z.re).collect()
+}
+
+// Cut the filter to taps samples starting at the trigger, normalise it so the template filters to 1 and
+// reverse it into FIR order
+fn fir(filter: &[f64], template: &[f64], start: usize, taps: usize) -> Vec<f64> {
+    let end = (start + taps).min(filter.len());
+    let window = &filter[start..end];
+    let gain: f64 = window.iter().zip(&template[start..end]).map(|(h, s)| h * s).sum();
+    window.iter().rev().map(|h| if gain != 0.0 { h / gain } else { 0.0 }).collect()
+}
+
+impl FilterBuilder {
+    // Build a filter for every channel with detected pulses
+    pub fn build(&mut self, pulses: &[PulseRecord], noise: &NoiseMeasurement, sample_rate: f64) -> Result<String, String> {
+        let taps = self.taps.trim().parse::<usize>().ok().filter(|t| *t > 0).ok_or_else(|| "Invalid number of taps.".to_string())?;
+
+        let mut by_channel: BTreeMap<usize, Vec<&PulseRecord>> = BTreeMap::new();
+        for p in pulses {
+            by_channel.entry(p.channel).or_default().push(p);
+        }
+        let Some(pre_trigger) = pulses.first().map(|p| p.pre_trigger) else {
+            return Err("Detect pulses first.".to_string());
+        };
+        if pulses.iter().any(|p| p.pre_trigger != pre_trigger) {
+            return Err("Pulses were recorded with different pre-trigger settings; clear and detect again.".to_string());
+        }
+
+        self.filters.clear();
+        for (channel, records) in by_channel {
+            let Some(template) = template(&records) else {
+                continue;
+            };
+            let psd = noise.phase_psd(channel);
+            let filter = optimal_filter(&template, psd.as_ref().map(|(f, p)| (f.as_slice(), p.as_slice())), sample_rate);
+            self.filters.insert(
+                channel,
+                ChannelFilter {
+                    coefficients: fir(&filter, &template, pre_trigger, taps),
+                    template,
+                    pulses: records.len(),
+                    white: psd.is_none(),
+                },
+            );
+        }
+        self.pre_trigger = pre_trigger;
+        self.sample_rate = sample_rate;
+        self.selected = self.filters.keys().next().copied();
+        Ok(format!("Built {} filters.", self.filters.len()))
+    }
+
+    // Write the coefficients as (channels, taps) float64 and Q15 int16 arrays with a JSON description
+    pub fn export(&self, path: &Path) -> Result<String, String> {
+        let taps = self.filters.values().map(|f| f.coefficients.len()).max().ok_or_else(|| "Build filters first.".to_string())?;
+        let filters: Vec<&ChannelFilter> = self.filters.values().collect();
+        let coefficient = |i: usize, j: usize| filters[i].coefficients.get(j).copied().unwrap_or(0.0);
+        let scales: Vec<f64> = filters.iter().map(|f| f.coefficients.iter().fold(0.0f64, |a, c| a.max(c.abs()))).collect();
+
+        let float = ndarray::Array2::from_shape_fn((filters.len(), taps), |(i, j)| coefficient(i, j));
+        let q15 = ndarray::Array2::from_shape_fn((filters.len(), taps), |(i, j)| {
+            if scales[i] > 0.0 {
+                (coefficient(i, j) / scales[i] * i16::MAX as f64).round() as i16
+            } else {
+                0
+            }
+        });
+        let metadata = FilterMetadata {
+            channels: self.filters.keys().copied().collect(),
+            taps,
+            sample_rate: self.sample_rate,
+            pre_trigger: self.pre_trigger,
+            q15_scale: scales,
+            white_noise: filters.iter().map(|f| f.white).collect(),
+        };
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let q15_path = path.with_file_name(format!("{}_q15.npy", stem));
+        let meta_path = path.with_file_name(format!("{}_meta.json", stem));
+        ndarray_npy::write_npy(path, &float).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        ndarray_npy::write_npy(&q15_path, &q15).map_err(|e| format!("Failed to write {}: {}", q15_path.display(), e))?;
+        serde_json::to_string_pretty(&metadata)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&meta_path, json))
+            .map_err(|e| format!("Failed to write {}: {}", meta_path.display(), e))?;
+        Ok(format!("Exported {} filters to {}, {} and {}", filters.len(), path.display(), q15_path.display(), meta_path.display()))
+    }
+
+    // Draw the optimal filter pane; returns true when filters should be (re)built
+    pub fn show(&mut self, ui: &mut egui::Ui, pulse_count: usize, noise_channels: usize) -> bool {
+        let mut build = false;
+
+        ui.label(format!("{} detected pulses, noise PSDs for {} channels", pulse_count, noise_channels));
+        ui.horizontal(|ui| {
+            ui.label("Taps:");
+            ui.text_edit_singleline(&mut self.taps);
+            if ui.button("Build Filters").clicked() {
+                build = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Export (.npy):");
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button("Export").clicked() {
+                self.message = Some(self.export(Path::new(self.export_path.trim())).unwrap_or_else(|e| e));
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for (channel, filter) in &self.filters {
+                let label = format!("Channel {} ({} pulses{})", channel, filter.pulses, if filter.white { ", white noise" } else { "" });
+                if ui.selectable_label(self.selected == Some(*channel), label).clicked() {
+                    self.selected = Some(*channel);
+                }
+            }
+        });
+
+        let Some(filter) = self.selected.and_then(|c| self.filters.get(&c)) else {
+            return build;
+        };
+        let rate = self.sample_rate;
+        let pre_trigger = self.pre_trigger as f64;
+        ui.columns(2, |columns| {
+            Plot::new("filter_template")
+                .height(250.0)
+                .legend(Legend::default())
+                .x_axis_label("Time from trigger (s)")
+                .show(&mut columns[0], |plot_ui| {
+                    let points: Vec<[f64; 2]> = filter.template.iter().enumerate().map(|(i, v)| [(i as f64 - pre_trigger) / rate, *v]).collect();
+                    plot_ui.line(Line::new(points).name("Template"));
+                });
+            Plot::new("filter_coefficients")
+                .height(250.0)
+                .legend(Legend::default())
+                .x_axis_label("Tap")
+                .show(&mut columns[1], |plot_ui| {
+                    let points: Vec<[f64; 2]> = filter.coefficients.iter().enumerate().map(|(i, c)| [i as f64, *c]).collect();
+                    plot_ui.line(Line::new(points).name("Coefficients"));
+                });
+        });
+
+        build
+    }
+}
@@ -0,0 +1,259 @@
+// Power sweep heatmap
+// Shows frequency vs attenuation for a multi-power sweep and lets the user pick each resonator's readout power
+// Called to in gui
+
+use crate::sweep_data::SweepTrace;
+use eframe::egui;
+use egui_plot::{MarkerShape, Plot, PlotImage, PlotPoint, Points};
+use gen3_rpc::Attens;
+
+// Colormaps offered for the heatmap
+pub const COLORMAPS: [(&str, colorous::Gradient); 6] = [
+    ("Viridis", colorous::VIRIDIS),
+    ("Inferno", colorous::INFERNO),
+    ("Magma", colorous::MAGMA),
+    ("Plasma", colorous::PLASMA),
+    ("Cividis", colorous::CIVIDIS),
+    ("Greys", colorous::GREYS),
+];
+
+// Quantity used to colour the heatmap
+#[derive(PartialEq, Clone, Copy)]
+pub enum Metric {
+    S21,        // |S21| in dB
+    IQVelocity, // |dIQ/df|
+}
+
+// A readout power chosen for one resonator
+#[derive(Clone)]
+pub struct ReadoutPower {
+    pub freq: f64,      // Resonator frequency picked on the map (Hz)
+    pub attens: Attens, // Attenuations of the chosen power setting
+    pub fft_scale: u16, // FFT scale of the chosen power setting
+    row: Option<usize>, // Row of the map it was picked on, None once that sweep is replaced
+}
+
+pub struct PowerMap {
+    pub metric: Metric,
+    pub colormap: usize,          // Index into COLORMAPS
+    pub normalize_rows: bool,     // Normalize each power setting separately
+    pub merge_window: String,     // Picks closer than this (Hz) replace each other
+    pub picks: Vec<ReadoutPower>, // Selected readout powers, sorted by frequency
+    rows: Vec<SweepTrace>,        // Traces sorted by output attenuation
+    texture: Option<egui::TextureHandle>,
+    dirty: bool, // Texture needs to be rebuilt
+}
+
+impl Default for PowerMap {
+    fn default() -> Self {
+        Self {
+            metric: Metric::S21,
+            colormap: 0,
+            normalize_rows: true,
+            merge_window: "100000".to_string(),
+            picks: Vec::new(),
+            rows: Vec::new(),
+            texture: None,
+            dirty: false,
+        }
+    }
+}
+
+impl PowerMap {
+    // Load the traces of a new sweep
+    pub fn set_traces(&mut self, traces: &[SweepTrace]) {
+        self.rows = traces.to_vec();
+        self.rows.sort_by(|a, b| a.setting.attens.output.total_cmp(&b.setting.attens.output));
+        // Earlier picks belong to another sweep's rows
+        for p in self.picks.iter_mut() {
+            p.row = None;
+        }
+        self.dirty = true;
+    }
+
+    // Value of the selected metric for each row
+    fn values(&self) -> Vec<Vec<f64>> {
+        self.rows
+            .iter()
+            .map(|t| match self.metric {
+                Metric::S21 => t.magnitude_db(),
+                Metric::IQVelocity => t.iq_velocity(),
+            })
+            .collect()
+    }
+
+    // Build the heatmap image; row 0 (lowest attenuation) is drawn at the bottom
+    fn build_image(&self) -> egui::ColorImage {
+        let values = self.values();
+        let width = values.iter().map(|v| v.len()).max().unwrap_or(0).max(1);
+        let height = values.len().max(1);
+        let gradient = COLORMAPS[self.colormap].1;
+
+        let range = |v: &[f64]| {
+            v.iter()
+                .filter(|x| x.is_finite())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)))
+        };
+        let global = range(&values.concat());
+
+        let mut pixels = vec![egui::Color32::BLACK; width * height];
+        for (row, v) in values.iter().enumerate() {
+            let (lo, hi) = if self.normalize_rows { range(v) } else { global };
+            let y = height - 1 - row;
+            for (x, value) in v.iter().enumerate() {
+                let t = if hi > lo { ((value - lo) / (hi - lo)).clamp(0.0, 1.0) } else { 0.0 };
+                let c = gradient.eval_continuous(t);
+                pixels[y * width + x] = egui::Color32::from_rgb(c.r, c.g, c.b);
+            }
+        }
+
+        egui::ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
+
+    // Record a pick, replacing any existing pick for the same resonator
+    fn pick(&mut self, freq: f64, row: usize) {
+        let window = self.merge_window.parse::<f64>().unwrap_or(0.0);
+        let setting = self.rows[row].setting;
+        self.picks.retain(|p| (p.freq - freq).abs() > window);
+        self.picks.push(ReadoutPower {
+            freq,
+            attens: setting.attens,
+            fft_scale: setting.fft_scale,
+            row: Some(row),
+        });
+        self.picks.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+    }
+
+    // Draw the heatmap pane
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        if self.rows.is_empty() {
+            ui.label("No power sweep available. Perform a sweep first.");
+            return;
+        }
+
+        // Display options
+        ui.horizontal(|ui| {
+            ui.label("Colour:");
+            self.dirty |= ui.radio_value(&mut self.metric, Metric::S21, "|S21| (dB)").changed();
+            self.dirty |= ui.radio_value(&mut self.metric, Metric::IQVelocity, "IQ Velocity").changed();
+
+            egui::ComboBox::from_label("Colormap")
+                .selected_text(COLORMAPS[self.colormap].0)
+                .show_ui(ui, |ui| {
+                    for (i, (name, _)) in COLORMAPS.iter().enumerate() {
+                        self.dirty |= ui.selectable_value(&mut self.colormap, i, *name).changed();
+                    }
+                });
+
+            self.dirty |= ui.checkbox(&mut self.normalize_rows, "Normalize each power").changed();
+        });
+
+        if self.dirty || self.texture.is_none() {
+            let image = self.build_image();
+            self.texture = Some(ui.ctx().load_texture("power_map", image, egui::TextureOptions::NEAREST));
+            self.dirty = false;
+        }
+
+        // Sweep points need not be evenly spaced (log spacing, windows, imported lists), so the X axis is the
+        // column index (column centres at integers) and is labelled with the frequency of that column
+        let freqs = self.rows[0].freqs.clone();
+        let n_cols = freqs.len().max(1);
+        let n_rows = self.rows.len();
+        let texture = self.texture.as_ref().unwrap().id();
+        let column = |f: f64| (0..freqs.len()).min_by(|&a, &b| (freqs[a] - f).abs().total_cmp(&(freqs[b] - f).abs()));
+
+        // Y axis is the row index (row centres at integers); label it with the output attenuation of that row
+        let labels: Vec<f32> = self.rows.iter().map(|t| t.setting.attens.output).collect();
+        let pick_points: Vec<[f64; 2]> = self
+            .picks
+            .iter()
+            .filter_map(|p| Some([column(p.freq)? as f64, p.row? as f64]))
+            .collect();
+        let axis_freqs = freqs.clone();
+
+        ui.label("Click on the map to select the readout power of the resonator under the cursor.");
+        let clicked = Plot::new("power_map_plot")
+            .height(400.0)
+            .x_axis_label("Frequency (MHz)")
+            .x_axis_formatter(move |mark, _range| {
+                let col = mark.value.round();
+                if col >= 0.0 && (col as usize) < axis_freqs.len() {
+                    format!("{:.3}", axis_freqs[col as usize] / 1e6)
+                } else {
+                    String::new()
+                }
+            })
+            .y_axis_label("Output Attenuation (dB)")
+            .y_axis_formatter(move |mark, _range| {
+                let row = mark.value.round();
+                if mark.value == row && row >= 0.0 && (row as usize) < labels.len() {
+                    labels[row as usize].to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .show(ui, |plot_ui| {
+                plot_ui.image(PlotImage::new(
+                    texture,
+                    PlotPoint::new((n_cols as f64 - 1.0) / 2.0, (n_rows as f64 - 1.0) / 2.0),
+                    egui::vec2(n_cols as f32, n_rows as f32),
+                ));
+                plot_ui.points(
+                    Points::new(pick_points)
+                        .shape(MarkerShape::Cross)
+                        .radius(6.0)
+                        .color(egui::Color32::WHITE)
+                        .name("Readout power"),
+                );
+
+                if plot_ui.response().clicked() {
+                    plot_ui.pointer_coordinate()
+                } else {
+                    None
+                }
+            })
+            .inner;
+
+        if let Some(point) = clicked {
+            let (col, row) = (point.x.round(), point.y.round());
+            if row >= 0.0 && (row as usize) < n_rows && col >= 0.0 && (col as usize) < freqs.len() {
+                self.pick(freqs[col as usize], row as usize);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Same-resonator window (Hz):");
+            ui.text_edit_singleline(&mut self.merge_window);
+        });
+
+        // Table of selected readout powers
+        let mut remove = None;
+        egui::Grid::new("readout_power_picks").striped(true).show(ui, |ui| {
+            ui.label("Frequency (MHz)");
+            ui.label("Input Atten");
+            ui.label("Output Atten");
+            ui.label("FFT Scale");
+            ui.end_row();
+
+            for (i, p) in self.picks.iter().enumerate() {
+                ui.label(format!("{:.6}", p.freq / 1e6));
+                ui.label(p.attens.input.to_string());
+                ui.label(p.attens.output.to_string());
+                ui.label(p.fft_scale.to_string());
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = remove {
+            self.picks.remove(i);
+        }
+        if !self.picks.is_empty() && ui.button("Clear Selections").clicked() {
+            self.picks.clear();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// Power sweep table editor
+// Builds the list of PowerSettings used by a single SweepConfig
+// Called to in gui
+
+use crate::gui::VALID_FFT_SCALES;
+use eframe::egui;
+use gen3_rpc::utils::client::PowerSetting;
+use gen3_rpc::Attens;
+
+// Most power settings a table may expand to
+pub const MAX_POWER_SETTINGS: usize = 10_000;
+
+// One row of the table: a range of output attenuations at a fixed input attenuation and FFT scale
+pub struct PowerRow {
+    pub input_atten: String,  // Input attenuation (dB)
+    pub output_start: String, // First output attenuation (dB)
+    pub output_stop: String,  // Last output attenuation (dB, inclusive)
+    pub output_step: String,  // Output attenuation step (dB)
+    pub fft_scale: String,    // FFT scale used for every setting in this row
+    summary: Option<Result<usize, String>>, // Cached count() of the current inputs, cleared on edit
+}
+
+impl Default for PowerRow {
+    fn default() -> Self {
+        Self {
+            input_atten: "0".to_string(),
+            output_start: "0".to_string(),
+            output_stop: "0".to_string(),
+            output_step: "1".to_string(),
+            fft_scale: "4095".to_string(),
+            summary: None,
+        }
+    }
+}
+
+// Parse a finite number ("nan" and "inf" parse as f32 but are not attenuations)
+fn parse_finite(text: &str, what: &str) -> Result<f32, String> {
+    text.trim().parse::<f32>().ok().filter(|v| v.is_finite()).ok_or_else(|| format!("Invalid {}.", what))
+}
+
+impl PowerRow {
+    // Parsed inputs: input attenuation, output start, output step (signed towards stop), number of settings and FFT scale
+    fn parse(&self) -> Result<(f32, f32, f32, usize, u16), String> {
+        let input = parse_finite(&self.input_atten, "input attenuation")?;
+        let start = parse_finite(&self.output_start, "output attenuation start")?;
+        let stop = parse_finite(&self.output_stop, "output attenuation stop")?;
+        let step = parse_finite(&self.output_step, "output attenuation step")?;
+        let fft_scale = self.fft_scale.trim().parse::<u16>().map_err(|_| "Invalid FFT scale.".to_string())?;
+
+        if !VALID_FFT_SCALES.contains(&fft_scale) {
+            return Err(format!("Invalid FFT scale {}. Please enter one of the valid values.", fft_scale));
+        }
+        if step <= 0.0 {
+            return Err("Output attenuation step must be > 0.".to_string());
+        }
+
+        // Walk from start towards stop (either direction), including stop when it lands on a step.
+        // Count in f64 and check it before converting so tiny steps can't saturate or overflow.
+        let steps = ((stop as f64 - start as f64).abs() / step as f64 + 1e-3).floor();
+        if steps + 1.0 > MAX_POWER_SETTINGS as f64 {
+            return Err(format!("More than {} settings; use a larger step.", MAX_POWER_SETTINGS));
+        }
+        let direction = if stop >= start { 1.0 } else { -1.0 };
+        Ok((input, start, direction * step, steps as usize + 1, fft_scale))
+    }
+
+    // Number of settings the row expands to, cached until the row is edited
+    pub fn count(&mut self) -> Result<usize, String> {
+        if self.summary.is_none() {
+            self.summary = Some(self.parse().map(|(_, _, _, count, _)| count));
+        }
+        self.summary.clone().unwrap()
+    }
+
+    // Expand the row into one PowerSetting per output attenuation step
+    pub fn settings(&self) -> Result<Vec<PowerSetting>, String> {
+        let (input, start, step, count, fft_scale) = self.parse()?;
+        Ok((0..count)
+            .map(|i| PowerSetting {
+                attens: Attens {
+                    input,
+                    output: start + step * i as f32,
+                },
+                fft_scale,
+            })
+            .collect())
+    }
+}
+
+// The full power sweep table
+pub struct PowerTable {
+    pub rows: Vec<PowerRow>,
+}
+
+impl Default for PowerTable {
+    fn default() -> Self {
+        Self {
+            rows: vec![PowerRow::default()], // Start with a single row so the table is never empty
+        }
+    }
+}
+
+impl PowerTable {
+    // Expand every row, in table order, into the settings list for SweepConfig
+    pub fn settings(&self) -> Result<Vec<PowerSetting>, String> {
+        let mut settings = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            let row_settings = row.settings().map_err(|e| format!("Row {}: {}", i + 1, e))?;
+            settings.extend(row_settings);
+            if settings.len() > MAX_POWER_SETTINGS {
+                return Err(format!("Table expands to more than {} settings.", MAX_POWER_SETTINGS));
+            }
+        }
+        if settings.is_empty() {
+            return Err("Power sweep table is empty.".to_string());
+        }
+        Ok(settings)
+    }
+
+    // Draw the table editor
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+
+        egui::Grid::new("power_sweep_table").striped(true).show(ui, |ui| {
+            ui.label("Input Atten");
+            ui.label("Output Start");
+            ui.label("Output Stop");
+            ui.label("Output Step");
+            ui.label("FFT Scale");
+            ui.label("Settings");
+            ui.end_row();
+
+            for (i, row) in self.rows.iter_mut().enumerate() {
+                let mut changed = false;
+                for text in [&mut row.input_atten, &mut row.output_start, &mut row.output_stop, &mut row.output_step, &mut row.fft_scale] {
+                    changed |= ui.add(egui::TextEdit::singleline(text).desired_width(60.0)).changed();
+                }
+                if changed {
+                    row.summary = None;
+                }
+
+                // Show how many settings the row expands to, or why it is invalid
+                match row.count() {
+                    Ok(count) => ui.label(count.to_string()),
+                    Err(e) => ui.colored_label(egui::Color32::RED, e),
+                };
+
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = remove {
+            self.rows.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add Row").clicked() {
+                self.rows.push(PowerRow::default());
+            }
+            let counts: Result<Vec<usize>, String> = self.rows.iter_mut().map(|r| r.count()).collect();
+            match counts.map(|c| c.iter().sum::<usize>()) {
+                Ok(total) if total > MAX_POWER_SETTINGS => {
+                    ui.colored_label(egui::Color32::RED, format!("Total power settings: {} (limit {})", total, MAX_POWER_SETTINGS));
+                }
+                Ok(total) => {
+                    ui.label(format!("Total power settings: {}", total));
+                }
+                Err(_) => {}
+            }
+        });
+    }
+}
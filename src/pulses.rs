@@ -0,0 +1,358 @@
+// Photon pulse detection
+// Triggers on its own phase timestream captures, builds pulse-height histograms per channel, fits the peaks for R = E/dE and exports pulse records
+// Called to in gui
+
+use crate::resonator_fit::solve;
+use crate::timestream::ChannelSamples;
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Legend, Line, Plot};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Oldest records are dropped beyond this
+const MAX_PULSES: usize = 10_000;
+
+// FWHM of a Gaussian in units of sigma
+const FWHM_PER_SIGMA: f64 = 2.354820045;
+
+// One detected pulse
+pub struct PulseRecord {
+    pub channel: usize,
+    pub capture: u64,      // Capture number since the detector was cleared
+    pub index: usize,      // Trigger sample within the capture
+    pub baseline: f64,     // Pre-trigger baseline (rad)
+    pub height: f64,       // Peak height above the baseline (rad, pulses made positive)
+    pub samples: Vec<f64>, // Baseline-subtracted record starting pre-trigger samples before the trigger
+    pub pre_trigger: usize, // Samples of the record before the trigger
+}
+
+// A Gaussian fitted to one pulse-height histogram peak
+pub struct Peak {
+    pub center: f64, // Mean pulse height (rad)
+    pub fwhm: f64,   // Full width at half maximum (rad)
+}
+
+impl Peak {
+    // Resolving power R = E / dE, pulse height taken as proportional to energy
+    pub fn resolution(&self) -> f64 {
+        self.center / self.fwhm
+    }
+}
+
+// Detection settings parsed from the pane inputs
+struct Trigger {
+    threshold: f64,
+    holdoff: usize,
+    baseline: usize,
+    pre_trigger: usize,
+    record_length: usize,
+    sign: f64,
+}
+
+pub struct PulseDetector {
+    pub live: bool,            // Capture again as soon as the last capture returns
+    pub threshold: String,     // Trigger level above the baseline (rad)
+    pub holdoff: String,       // Samples after a trigger before the next one is allowed
+    pub baseline: String,      // Samples averaged before the trigger for the baseline
+    pub pre_trigger: String,   // Samples kept before the trigger in each record
+    pub record_length: String, // Samples per record
+    pub negative: bool,        // Pulses are negative-going
+    pub bins: String,          // Histogram bins
+    pub export_path: String,   // npy file for the records
+    pub channel: usize,        // Channel shown in the histogram
+    pub pulses: Vec<PulseRecord>,
+    pub message: Option<String>,
+    captures: u64,
+}
+
+impl Default for PulseDetector {
+    fn default() -> Self {
+        Self {
+            live: false,
+            threshold: "0.1".to_string(),
+            holdoff: "200".to_string(),
+            baseline: "100".to_string(),
+            pre_trigger: "50".to_string(),
+            record_length: "500".to_string(),
+            negative: true,
+            bins: "100".to_string(),
+            export_path: "pulses.npy".to_string(),
+            channel: 0,
+            pulses: Vec::new(),
+            message: None,
+            captures: 0,
+        }
+    }
+}
+
+// Find pulses in one capture of one channel
+fn detect(channel: usize, capture: u64, phase: &[f64], trigger: &Trigger) -> Vec<PulseRecord> {
+    let y: Vec<f64> = phase.iter().map(|p| trigger.sign * p).collect();
+    let post = trigger.record_length - trigger.pre_trigger;
+    let first = trigger.baseline.max(trigger.pre_trigger);
+    let mut pulses = Vec::new();
+    let mut i = first;
+    while i + post <= y.len() {
+        let baseline = y[i - trigger.baseline..i].iter().sum::<f64>() / trigger.baseline as f64;
+        if y[i] - baseline <= trigger.threshold {
+            i += 1;
+            continue;
+        }
+        let record = &y[i - trigger.pre_trigger..i + post];
+        pulses.push(PulseRecord {
+            channel,
+            capture,
+            index: i,
+            baseline: trigger.sign * baseline,
+            height: y[i..i + post].iter().fold(f64::MIN, |a, b| a.max(*b)) - baseline,
+            samples: record.iter().map(|v| v - baseline).collect(),
+            pre_trigger: trigger.pre_trigger,
+        });
+        i += trigger.holdoff.max(1);
+    }
+    pulses
+}
+
+// Histogram of values: (lowest edge, bin width, counts)
+fn histogram(values: &[f64], bins: usize) -> (f64, f64, Vec<f64>) {
+    let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = if hi > lo { (hi - lo) / bins as f64 } else { 1.0 };
+    let mut counts = vec![0.0; bins];
+    for v in values {
+        counts[(((v - lo) / width) as usize).min(bins - 1)] += 1.0;
+    }
+    (lo, width, counts)
+}
+
+// Fit a Gaussian to every histogram peak above a fifth of the tallest one. Peaks and their extent
+// are found on a 5-bin moving average (bins falling away monotonically from the peak), then each
+// fit uses the log-parabola (Caruana) method on the raw counts.
+fn fit_peaks(lo: f64, width: f64, counts: &[f64]) -> Vec<Peak> {
+    let n = counts.len();
+    let smooth: Vec<f64> = (0..n)
+        .map(|i| {
+            let window = &counts[i.saturating_sub(2)..(i + 3).min(n)];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect();
+    let max = smooth.iter().copied().fold(0.0, f64::max);
+    let is_peak = |i: usize| (i == 0 || smooth[i] >= smooth[i - 1]) && (i + 1 == n || smooth[i] >= smooth[i + 1]);
+    let mut order: Vec<usize> = (0..n).filter(|&i| smooth[i] >= 0.2 * max && smooth[i] > 0.0 && is_peak(i)).collect();
+    order.sort_by(|a, b| smooth[*b].total_cmp(&smooth[*a]));
+
+    let mut used = vec![false; counts.len()];
+    let mut peaks = Vec::new();
+    for top in order {
+        if used[top] {
+            continue;
+        }
+        let floor = 0.1 * smooth[top];
+        let (mut a, mut b) = (top, top);
+        while a > 0 && smooth[a - 1] > floor && smooth[a - 1] <= smooth[a] && !used[a - 1] {
+            a -= 1;
+        }
+        while b + 1 < n && smooth[b + 1] > floor && smooth[b + 1] <= smooth[b] && !used[b + 1] {
+            b += 1;
+        }
+        used[a..=b].iter_mut().for_each(|u| *u = true);
+        if b - a < 2 {
+            continue;
+        }
+
+        // Weighted least squares of ln(count) = p0 + p1 x + p2 x^2, x in bins from the peak
+        let mut m = [[0.0; 3]; 3];
+        let mut v = [0.0; 3];
+        for (i, &count) in counts.iter().enumerate().take(b + 1).skip(a).filter(|(_, c)| **c > 0.0) {
+            let x = i as f64 - top as f64;
+            let row = [1.0, x, x * x];
+            let w = count * count;
+            for r in 0..3 {
+                for c in 0..3 {
+                    m[r][c] += w * row[r] * row[c];
+                }
+                v[r] += w * row[r] * count.ln();
+            }
+        }
+        let Some(p) = solve(m, v) else {
+            continue;
+        };
+        if p[2] >= 0.0 {
+            continue;
+        }
+        let mean = top as f64 - p[1] / (2.0 * p[2]);
+        let sigma = (-1.0 / (2.0 * p[2])).sqrt();
+        peaks.push(Peak {
+            center: lo + (mean + 0.5) * width,
+            fwhm: FWHM_PER_SIGMA * sigma * width,
+        });
+    }
+    peaks.sort_by(|a, b| a.center.total_cmp(&b.center));
+    peaks
+}
+
+impl PulseDetector {
+    fn trigger(&self) -> Result<Trigger, String> {
+        let parse = |s: &str| s.trim().parse::<usize>().ok();
+        let (Ok(threshold), Some(holdoff), Some(baseline), Some(pre_trigger), Some(record_length)) = (
+            self.threshold.trim().parse::<f64>(),
+            parse(&self.holdoff),
+            parse(&self.baseline),
+            parse(&self.pre_trigger),
+            parse(&self.record_length),
+        ) else {
+            return Err("Invalid pulse detection settings.".to_string());
+        };
+        if baseline == 0 || pre_trigger >= record_length {
+            return Err("Baseline must be at least one sample and the pre-trigger shorter than the record.".to_string());
+        }
+        Ok(Trigger {
+            threshold,
+            holdoff,
+            baseline,
+            pre_trigger,
+            record_length,
+            sign: if self.negative { -1.0 } else { 1.0 },
+        })
+    }
+
+    // Run detection on the phase of each channel in the last capture
+    pub fn process(&mut self, latest: &[ChannelSamples]) {
+        let trigger = match self.trigger() {
+            Ok(trigger) => trigger,
+            Err(e) => {
+                self.message = Some(e);
+                self.live = false;
+                return;
+            }
+        };
+        for samples in latest {
+            self.pulses.extend(detect(samples.channel, self.captures, &samples.phase, &trigger));
+        }
+        let excess = self.pulses.len().saturating_sub(MAX_PULSES);
+        self.pulses.drain(..excess);
+        self.captures += 1;
+    }
+
+    // Write the records as an (n_pulses, record_length) array and their channel, capture, index,
+    // baseline and height as an (n_pulses, 5) array next to it
+    pub fn export(&self, path: &Path) -> Result<String, String> {
+        let length = self.pulses.first().map_or(0, |p| p.samples.len());
+        if length == 0 || self.pulses.iter().any(|p| p.samples.len() != length) {
+            return Err("No pulses to export, or records of different lengths (clear after changing settings).".to_string());
+        }
+        let records = ndarray::Array2::from_shape_fn((self.pulses.len(), length), |(i, j)| self.pulses[i].samples[j]);
+        let info = ndarray::Array2::from_shape_fn((self.pulses.len(), 5), |(i, j)| {
+            let p = &self.pulses[i];
+            [p.channel as f64, p.capture as f64, p.index as f64, p.baseline, p.height][j]
+        });
+        let info_path = path.with_file_name(format!("{}_info.npy", path.file_stem().unwrap_or_default().to_string_lossy()));
+        ndarray_npy::write_npy(path, &records).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        ndarray_npy::write_npy(&info_path, &info).map_err(|e| format!("Failed to write {}: {}", info_path.display(), e))?;
+        Ok(format!("Exported {} pulses to {} and {}", self.pulses.len(), path.display(), info_path.display()))
+    }
+
+    // Draw the pulse detection pane
+    // Draw the pulse pane; busy is true while a timestream capture is in flight. Returns true when a capture was requested.
+    pub fn show(&mut self, ui: &mut egui::Ui, busy: bool) -> bool {
+        let mut capture = false;
+
+        ui.label("Captures use the channels, length and sample rate of the Timestream pane.");
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!busy, egui::Button::new("Capture")).clicked() {
+                capture = true;
+            }
+            ui.checkbox(&mut self.live, "Live");
+        });
+        egui::Grid::new("pulse_settings").show(ui, |ui| {
+            for (label, value) in [
+                ("Threshold (rad):", &mut self.threshold),
+                ("Holdoff (samples):", &mut self.holdoff),
+                ("Baseline (samples):", &mut self.baseline),
+                ("Pre-trigger (samples):", &mut self.pre_trigger),
+                ("Record Length (samples):", &mut self.record_length),
+                ("Histogram Bins:", &mut self.bins),
+            ] {
+                ui.label(label);
+                ui.text_edit_singleline(value);
+                ui.end_row();
+            }
+        });
+        ui.checkbox(&mut self.negative, "Negative-going pulses");
+
+        ui.horizontal(|ui| {
+            ui.label("Export (.npy):");
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button("Export").clicked() {
+                self.message = Some(self.export(Path::new(self.export_path.trim())).unwrap_or_else(|e| e));
+            }
+            if ui.button("Clear").clicked() {
+                self.pulses.clear();
+                self.captures = 0;
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Pulse counts per channel
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for p in &self.pulses {
+            *counts.entry(p.channel).or_default() += 1;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.label(format!("{} pulses in {} captures:", self.pulses.len(), self.captures));
+            for (channel, count) in &counts {
+                if ui.selectable_label(self.channel == *channel, format!("Channel {} ({})", channel, count)).clicked() {
+                    self.channel = *channel;
+                }
+            }
+        });
+
+        // Pulse-height histogram of the selected channel with the fitted peaks
+        let heights: Vec<f64> = self.pulses.iter().filter(|p| p.channel == self.channel).map(|p| p.height).collect();
+        if heights.is_empty() {
+            return capture;
+        }
+        let bins = self.bins.trim().parse::<usize>().unwrap_or(100).max(1);
+        let (lo, width, counts) = histogram(&heights, bins);
+        let peaks = fit_peaks(lo, width, &counts);
+
+        for (i, peak) in peaks.iter().enumerate() {
+            ui.label(format!(
+                "Peak {}: height {:.4} rad, FWHM {:.4} rad, R = {:.1}",
+                i + 1,
+                peak.center,
+                peak.fwhm,
+                peak.resolution()
+            ));
+        }
+
+        let bars: Vec<Bar> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Bar::new(lo + (i as f64 + 0.5) * width, *c).width(width))
+            .collect();
+        Plot::new("pulse_height_histogram")
+            .height(300.0)
+            .legend(Legend::default())
+            .x_axis_label("Pulse Height (rad)")
+            .y_axis_label("Counts")
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).name(format!("Channel {}", self.channel)));
+                for (i, peak) in peaks.iter().enumerate() {
+                    // Scale the Gaussian to the counts at its centre bin
+                    let bin = (((peak.center - lo) / width) as usize).min(counts.len() - 1);
+                    let sigma = peak.fwhm / FWHM_PER_SIGMA;
+                    let curve: Vec<[f64; 2]> = (0..=200)
+                        .map(|k| peak.center + (k as f64 / 100.0 - 1.0) * 3.0 * sigma)
+                        .map(|x| [x, counts[bin] * (-0.5 * ((x - peak.center) / sigma).powi(2)).exp()])
+                        .collect();
+                    plot_ui.line(Line::new(curve).name(format!("Peak {} fit", i + 1)));
+                }
+            });
+
+        capture
+    }
+}
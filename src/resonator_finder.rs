@@ -0,0 +1,335 @@
+// Automatic resonator finding
+// Detects resonances in sweep data (dips in |S21| and/or peaks in IQ velocity) and exports the list
+// Called to in gui
+
+use crate::sweep_data::SweepTrace;
+use eframe::egui;
+use egui_plot::{Line, MarkerShape, Plot, Points};
+use serde::Serialize;
+use std::io::Write;
+
+// Which features of the sweep count as a resonance
+#[derive(PartialEq, Clone, Copy)]
+pub enum FindMethod {
+    Dips,     // Dips in |S21| below the baseline
+    Velocity, // Peaks in IQ velocity
+    Both,     // Either of the above
+}
+
+// A detected resonance
+#[derive(Clone, Serialize)]
+pub struct FoundResonator {
+    pub freq: f64,     // Resonance frequency (Hz)
+    pub depth_db: f64, // Dip depth below the baseline (dB)
+    pub q: f64,        // Approximate loaded Q from the dip FWHM (NaN when it cannot be measured)
+}
+
+// Detection thresholds (Strings to handle text input)
+pub struct FinderConfig {
+    pub method: FindMethod,
+    pub min_depth_db: String,     // Minimum dip depth below the baseline (dB)
+    pub velocity_factor: String,  // Minimum IQ velocity as a multiple of the median velocity
+    pub baseline_window: String,  // Running median window for the |S21| baseline (points)
+    pub min_separation: String,   // Detections closer than this are merged (Hz)
+}
+
+impl Default for FinderConfig {
+    fn default() -> Self {
+        Self {
+            method: FindMethod::Dips,
+            min_depth_db: "3".to_string(),
+            velocity_factor: "5".to_string(),
+            baseline_window: "51".to_string(),
+            min_separation: "50000".to_string(),
+        }
+    }
+}
+
+// Parsed thresholds
+struct Thresholds {
+    min_depth_db: f64,
+    velocity_factor: f64,
+    baseline_window: usize,
+    min_separation: f64,
+}
+
+impl FinderConfig {
+    fn thresholds(&self) -> Result<Thresholds, String> {
+        let parse = |v: &str, name: &str| v.trim().parse::<f64>().map_err(|_| format!("Invalid {}.", name));
+        Ok(Thresholds {
+            min_depth_db: parse(&self.min_depth_db, "minimum depth")?,
+            velocity_factor: parse(&self.velocity_factor, "velocity factor")?,
+            baseline_window: self
+                .baseline_window
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|w| *w > 0)
+                .ok_or("Invalid baseline window.")?,
+            min_separation: parse(&self.min_separation, "minimum separation")?,
+        })
+    }
+}
+
+// Running median of v over a centred window. The window is kept sorted and updated by one insert and one
+// removal per sample instead of being re-sorted.
+fn running_median(v: &[f64], window: usize) -> Vec<f64> {
+    let half = window / 2;
+    let mut sorted: Vec<f64> = Vec::with_capacity(2 * half + 1);
+    let (mut lo, mut hi) = (0, 0);
+    (0..v.len())
+        .map(|i| {
+            while hi < (i + half + 1).min(v.len()) {
+                let at = sorted.partition_point(|x| x.total_cmp(&v[hi]).is_lt());
+                sorted.insert(at, v[hi]);
+                hi += 1;
+            }
+            while lo < i.saturating_sub(half) {
+                let at = sorted.partition_point(|x| x.total_cmp(&v[lo]).is_lt());
+                sorted.remove(at);
+                lo += 1;
+            }
+            sorted[sorted.len() / 2]
+        })
+        .collect()
+}
+
+// Index of the maximum of score within each contiguous run where mask is set
+fn run_peaks(mask: &[bool], score: &[f64]) -> Vec<usize> {
+    let mut peaks = Vec::new();
+    let mut best: Option<usize> = None;
+    for i in 0..mask.len() {
+        if mask[i] {
+            if best.is_none_or(|b| score[i] > score[b]) {
+                best = Some(i);
+            }
+        } else if let Some(b) = best.take() {
+            peaks.push(b);
+        }
+    }
+    peaks.extend(best);
+    peaks
+}
+
+// Loaded Q from the full width at half depth (in power) of the dip at index i
+fn dip_q(freqs: &[f64], mag: &[f64], baseline: &[f64], i: usize) -> f64 {
+    let half = (mag[i].powi(2) + baseline[i].powi(2)) / 2.0;
+    let left = (0..i).rev().find(|&j| mag[j].powi(2) >= half);
+    let right = (i + 1..mag.len()).find(|&j| mag[j].powi(2) >= half);
+    match (left, right) {
+        (Some(l), Some(r)) if freqs[r] > freqs[l] => freqs[i] / (freqs[r] - freqs[l]),
+        _ => f64::NAN,
+    }
+}
+
+// Find resonances in a single sweep trace
+pub fn find_resonators(trace: &SweepTrace, config: &FinderConfig) -> Result<Vec<FoundResonator>, String> {
+    let t = config.thresholds()?;
+    let n = trace.iq.len();
+    if n < 3 {
+        return Err("Sweep has too few points to search for resonators.".to_string());
+    }
+
+    let mag_db = trace.magnitude_db();
+    let baseline_db = running_median(&mag_db, t.baseline_window);
+    let depth: Vec<f64> = baseline_db.iter().zip(&mag_db).map(|(b, m)| b - m).collect();
+
+    let mut candidates = Vec::new();
+    if config.method != FindMethod::Velocity {
+        let mask: Vec<bool> = depth.iter().map(|d| *d >= t.min_depth_db).collect();
+        candidates.extend(run_peaks(&mask, &depth));
+    }
+    if config.method != FindMethod::Dips {
+        let velocity = trace.iq_velocity();
+        let mut sorted = velocity.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[n / 2];
+        let mask: Vec<bool> = velocity.iter().map(|v| median > 0.0 && *v >= t.velocity_factor * median).collect();
+        candidates.extend(run_peaks(&mask, &velocity));
+    }
+
+    // Merge detections closer than the minimum separation, keeping the deepest
+    candidates.sort_unstable();
+    candidates.dedup();
+    let mut kept: Vec<usize> = Vec::new();
+    for i in candidates {
+        match kept.last_mut() {
+            Some(last) if trace.freqs[i] - trace.freqs[*last] < t.min_separation => {
+                if depth[i] > depth[*last] {
+                    *last = i;
+                }
+            }
+            _ => kept.push(i),
+        }
+    }
+
+    let mag: Vec<f64> = mag_db.iter().map(|m| 10f64.powf(m / 20.0)).collect();
+    let baseline: Vec<f64> = baseline_db.iter().map(|b| 10f64.powf(b / 20.0)).collect();
+    Ok(kept
+        .into_iter()
+        .map(|i| FoundResonator {
+            freq: trace.freqs[i],
+            depth_db: depth[i],
+            q: dip_q(&trace.freqs, &mag, &baseline, i),
+        })
+        .collect())
+}
+
+// Write the resonator list as CSV
+pub fn export_csv(path: &str, resonators: &[FoundResonator]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    writeln!(file, "freq_hz,depth_db,q")?;
+    for r in resonators {
+        writeln!(file, "{},{},{}", r.freq, r.depth_db, r.q)?;
+    }
+    Ok(())
+}
+
+// Write the resonator list as JSON
+pub fn export_json(path: &str, resonators: &[FoundResonator]) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(resonators)?;
+    std::fs::write(path, json)
+}
+
+// Resonator finder pane state
+pub struct ResonatorFinder {
+    pub config: FinderConfig,
+    pub trace_index: usize,              // Power setting to search
+    pub found: Vec<FoundResonator>,      // Last detection result
+    pub export_path: String,             // File for CSV/JSON export
+    pub message: Option<String>,         // Error or export status
+}
+
+impl Default for ResonatorFinder {
+    fn default() -> Self {
+        Self {
+            config: FinderConfig::default(),
+            trace_index: 0,
+            found: Vec::new(),
+            export_path: "resonators.csv".to_string(),
+            message: None,
+        }
+    }
+}
+
+impl ResonatorFinder {
+    // Draw the finder pane for the traces of the last sweep
+    pub fn show(&mut self, ui: &mut egui::Ui, traces: &[SweepTrace]) {
+        if traces.is_empty() {
+            ui.label("No sweep available. Perform a sweep first.");
+            return;
+        }
+        self.trace_index = self.trace_index.min(traces.len() - 1);
+
+        // Power setting to analyse
+        let label = |t: &SweepTrace| format!("In {} dB / Out {} dB / Scale {}", t.setting.attens.input, t.setting.attens.output, t.setting.fft_scale);
+        egui::ComboBox::from_label("Power Setting")
+            .selected_text(label(&traces[self.trace_index]))
+            .show_ui(ui, |ui| {
+                for (i, t) in traces.iter().enumerate() {
+                    ui.selectable_value(&mut self.trace_index, i, label(t));
+                }
+            });
+
+        // Detection settings
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Detect:");
+                ui.radio_value(&mut self.config.method, FindMethod::Dips, "|S21| Dips");
+                ui.radio_value(&mut self.config.method, FindMethod::Velocity, "IQ Velocity Peaks");
+                ui.radio_value(&mut self.config.method, FindMethod::Both, "Both");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Minimum Dip Depth (dB):");
+                ui.text_edit_singleline(&mut self.config.min_depth_db);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Velocity Threshold (x median):");
+                ui.text_edit_singleline(&mut self.config.velocity_factor);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Baseline Window (points):");
+                ui.text_edit_singleline(&mut self.config.baseline_window);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Minimum Separation (Hz):");
+                ui.text_edit_singleline(&mut self.config.min_separation);
+            });
+
+            if ui.button("Find Resonators").clicked() {
+                match find_resonators(&traces[self.trace_index], &self.config) {
+                    Ok(found) => {
+                        self.message = Some(format!("Found {} resonators.", found.len()));
+                        self.found = found;
+                    }
+                    Err(e) => self.message = Some(e),
+                }
+            }
+        });
+
+        // Sweep plot with the detections overlaid
+        let trace = &traces[self.trace_index];
+        let mag_db = trace.magnitude_db();
+        let line: Vec<[f64; 2]> = trace.freqs.iter().zip(&mag_db).map(|(f, m)| [f / 1e6, *m]).collect();
+        let marks: Vec<[f64; 2]> = self
+            .found
+            .iter()
+            .map(|r| {
+                let i = trace.freqs.partition_point(|f| *f < r.freq).min(mag_db.len() - 1);
+                [r.freq / 1e6, mag_db[i]]
+            })
+            .collect();
+        Plot::new("resonator_finder_plot")
+            .height(300.0)
+            .x_axis_label("Frequency (MHz)")
+            .y_axis_label("|S21| (dB)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(line).name("|S21|"));
+                plot_ui.points(
+                    Points::new(marks)
+                        .shape(MarkerShape::Down)
+                        .radius(5.0)
+                        .color(egui::Color32::RED)
+                        .name("Resonators"),
+                );
+            });
+
+        // Export
+        ui.horizontal(|ui| {
+            ui.label("Export File:");
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button("Export CSV").clicked() {
+                self.message = Some(match export_csv(&self.export_path, &self.found) {
+                    Ok(()) => format!("Exported {} resonators to {}", self.found.len(), self.export_path),
+                    Err(e) => format!("Failed to export resonators: {}", e),
+                });
+            }
+            if ui.button("Export JSON").clicked() {
+                self.message = Some(match export_json(&self.export_path, &self.found) {
+                    Ok(()) => format!("Exported {} resonators to {}", self.found.len(), self.export_path),
+                    Err(e) => format!("Failed to export resonators: {}", e),
+                });
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Detected resonators
+        egui::ScrollArea::vertical().id_salt("found_resonators").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("found_resonators_grid").striped(true).show(ui, |ui| {
+                ui.label("Frequency (MHz)");
+                ui.label("Depth (dB)");
+                ui.label("Q");
+                ui.end_row();
+                for r in &self.found {
+                    ui.label(format!("{:.6}", r.freq / 1e6));
+                    ui.label(format!("{:.2}", r.depth_db));
+                    ui.label(if r.q.is_finite() { format!("{:.0}", r.q) } else { "-".to_string() });
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
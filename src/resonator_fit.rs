@@ -0,0 +1,461 @@
+// Resonator loop fitting
+// Fits the complex S21 resonator model (with cable delay, gain/phase background and nonlinearity) to sweep data
+// Called to in gui
+
+use crate::resonator_finder::FoundResonator;
+use crate::sweep_data::SweepTrace;
+use crate::targeted_sweep::ResonatorSweep;
+use eframe::egui;
+use egui_plot::{Line, Plot, Points};
+use num::Complex;
+use std::f64::consts::PI;
+
+// Number of model parameters
+const N_PARAMS: usize = 8;
+
+// Fitted resonator parameters
+#[derive(Clone)]
+pub struct FitResult {
+    pub f0: f64,       // Resonance frequency (Hz)
+    pub qr: f64,       // Loaded (total) quality factor
+    pub qc: f64,       // Coupling quality factor |Qc|
+    pub qi: f64,       // Internal quality factor
+    pub phi: f64,      // Impedance mismatch (asymmetry) angle of Qc (rad)
+    pub a_nl: f64,     // Nonlinearity (bifurcation) parameter, ~0.77 at bifurcation
+    pub gain: f64,     // Background gain
+    pub phase: f64,    // Background phase (rad)
+    pub delay: f64,    // Cable delay (s)
+    pub residual: f64, // RMS residual relative to the background gain
+}
+
+impl FitResult {
+    // Model S21 at the given frequencies
+    pub fn model(&self, freqs: &[f64]) -> Vec<Complex<f64>> {
+        freqs
+            .iter()
+            .map(|&f| s21(f, self.f0, self.qr, self.qc, self.phi, self.a_nl, self.gain, self.phase, self.delay))
+            .collect()
+    }
+}
+
+// Largest real root of a*y^3 + b*y^2 + c*y + d = 0
+fn largest_real_root(a: f64, b: f64, c: f64, d: f64) -> f64 {
+    // Depressed cubic t^3 + p*t + q = 0 with y = t - b/(3a)
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = b / 3.0;
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let disc = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+
+    let t = if disc > 0.0 {
+        // One real root
+        let s = disc.sqrt();
+        (-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt()
+    } else {
+        // Three real roots, take the largest
+        let r = (-p / 3.0).sqrt();
+        let theta = (-q / (2.0 * r.powi(3))).clamp(-1.0, 1.0).acos();
+        2.0 * r * (theta / 3.0).cos()
+    };
+    t - shift
+}
+
+// Complex S21 of a (possibly nonlinear) resonator with gain, phase and cable delay background.
+// The nonlinear detuning y solves y = y0 + a_nl / (1 + 4 y^2); the upper branch is used (increasing frequency sweep).
+#[allow(clippy::too_many_arguments)]
+fn s21(f: f64, f0: f64, qr: f64, qc: f64, phi: f64, a_nl: f64, gain: f64, phase: f64, delay: f64) -> Complex<f64> {
+    let y0 = qr * (f - f0) / f0;
+    let y = if a_nl > 0.0 {
+        largest_real_root(4.0, -4.0 * y0, 1.0, -y0 - a_nl)
+    } else {
+        y0
+    };
+    let qc_hat = Complex::from_polar(qc, -phi);
+    let resonance = Complex::new(1.0, 0.0) - Complex::new(qr, 0.0) / qc_hat / Complex::new(1.0, 2.0 * y);
+    Complex::from_polar(gain, phase - 2.0 * PI * f * delay) * resonance
+}
+
+// Solve the linear system m * x = v in place by Gaussian elimination with partial pivoting
+pub fn solve<const N: usize>(mut m: [[f64; N]; N], mut v: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        m.swap(col, pivot);
+        v.swap(col, pivot);
+        for row in col + 1..N {
+            let factor = m[row][col] / m[col][col];
+            let pivot_row = m[col];
+            for (x, p) in m[row].iter_mut().zip(pivot_row).skip(col) {
+                *x -= factor * p;
+            }
+            v[row] -= factor * v[col];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (v[row] - sum) / m[row][row];
+    }
+    Some(x)
+}
+
+// Least-squares fit of the resonator model to one resonance.
+// Parameters are scaled so every one is O(1): f0 offset in initial linewidths, ln Qr, ln Qc, phi, a_nl,
+// gain relative to the initial guess, phase at the window centre, and delay times the window span.
+pub fn fit_resonator(freqs: &[f64], iq: &[Complex<f64>], fit_nonlinear: bool) -> Result<FitResult, String> {
+    let n = freqs.len();
+    if n < 2 * N_PARAMS || iq.len() != n {
+        return Err(format!("Too few points to fit ({}).", n));
+    }
+    let span = freqs[n - 1] - freqs[0];
+    if span <= 0.0 {
+        return Err("Fit window has zero span.".to_string());
+    }
+
+    // Cable delay from the phase slope between the two edges of the window
+    let edge = (n / 10).max(2);
+    let phases: Vec<f64> = {
+        let mut unwrapped = Vec::with_capacity(n);
+        let mut offset = 0.0;
+        let mut last = iq[0].arg();
+        for z in iq {
+            let p = z.arg();
+            let d = p - last;
+            if d > PI {
+                offset -= 2.0 * PI;
+            } else if d < -PI {
+                offset += 2.0 * PI;
+            }
+            last = p;
+            unwrapped.push(p + offset);
+        }
+        unwrapped
+    };
+    let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+    let (f_lo, f_hi) = (mean(&freqs[..edge]), mean(&freqs[n - edge..]));
+    let (p_lo, p_hi) = (mean(&phases[..edge]), mean(&phases[n - edge..]));
+    let delay0 = -(p_hi - p_lo) / (2.0 * PI * (f_hi - f_lo));
+
+    // Gain and phase from the edges with the delay removed
+    let undelayed: Vec<Complex<f64>> = freqs
+        .iter()
+        .zip(iq)
+        .map(|(&f, &z)| z * Complex::from_polar(1.0, 2.0 * PI * f * delay0))
+        .collect();
+    let edges: Complex<f64> = undelayed[..edge].iter().chain(&undelayed[n - edge..]).sum::<Complex<f64>>() / (2 * edge) as f64;
+    let gain0 = edges.norm().max(f64::MIN_POSITIVE);
+    let phase0 = edges.arg();
+
+    // Resonance frequency, loaded Q and coupling Q from the dip
+    let mag: Vec<f64> = undelayed.iter().map(|z| z.norm() / gain0).collect();
+    let i_min = (0..n).min_by(|&a, &b| mag[a].total_cmp(&mag[b])).unwrap();
+    let f00 = freqs[i_min];
+    let half = (mag[i_min].powi(2) + 1.0) / 2.0;
+    let left = (0..i_min).rev().find(|&j| mag[j].powi(2) >= half).unwrap_or(0);
+    let right = (i_min + 1..n).find(|&j| mag[j].powi(2) >= half).unwrap_or(n - 1);
+    let fwhm = (freqs[right] - freqs[left]).max(span / n as f64);
+    let qr0 = f00 / fwhm;
+    let qc0 = qr0 / (1.0 - mag[i_min]).clamp(0.01, 0.99);
+    let linewidth = f00 / qr0;
+    let f_ref = (freqs[0] + freqs[n - 1]) / 2.0; // Background phase is fitted at the window centre
+
+    // Unscale the parameter vector
+    let unpack = |p: &[f64; N_PARAMS]| FitResult {
+        f0: f00 + p[0] * linewidth,
+        qr: p[1].exp(),
+        qc: p[2].exp(),
+        qi: 0.0,
+        phi: p[3],
+        a_nl: p[4].max(0.0),
+        gain: gain0 * p[5],
+        phase: p[6] + 2.0 * PI * f_ref * p[7] / span,
+        delay: p[7] / span,
+        residual: 0.0,
+    };
+    let residuals = |p: &[f64; N_PARAMS]| -> Vec<f64> {
+        let r = unpack(p);
+        freqs
+            .iter()
+            .zip(iq)
+            .flat_map(|(&f, &z)| {
+                let d = (s21(f, r.f0, r.qr, r.qc, r.phi, r.a_nl, r.gain, r.phase, r.delay) - z) / gain0;
+                [d.re, d.im]
+            })
+            .collect()
+    };
+    let cost = |r: &[f64]| r.iter().map(|x| x * x).sum::<f64>();
+
+    let mut p = [0.0, qr0.ln(), qc0.ln(), 0.0, 0.0, 1.0, phase0 - 2.0 * PI * f_ref * delay0, delay0 * span];
+    let free: [bool; N_PARAMS] = [true, true, true, true, fit_nonlinear, true, true, true];
+    let mut r = residuals(&p);
+    let mut c = cost(&r);
+    let mut lambda = 1e-3;
+
+    // Levenberg-Marquardt with a forward-difference Jacobian
+    for _ in 0..200 {
+        let h = 1e-6;
+        let mut jac = vec![vec![0.0; r.len()]; N_PARAMS];
+        for k in (0..N_PARAMS).filter(|&k| free[k]) {
+            let mut pk = p;
+            pk[k] += h;
+            let rk = residuals(&pk);
+            for (j, (a, b)) in rk.iter().zip(&r).enumerate() {
+                jac[k][j] = (a - b) / h;
+            }
+        }
+
+        let mut jtj = [[0.0; N_PARAMS]; N_PARAMS];
+        let mut jtr = [0.0; N_PARAMS];
+        for a in 0..N_PARAMS {
+            for b in 0..N_PARAMS {
+                jtj[a][b] = jac[a].iter().zip(&jac[b]).map(|(x, y)| x * y).sum();
+            }
+            jtr[a] = -jac[a].iter().zip(&r).map(|(x, y)| x * y).sum::<f64>();
+        }
+
+        let mut improved = false;
+        while lambda < 1e10 {
+            let mut m = jtj;
+            for (k, row) in m.iter_mut().enumerate() {
+                // Fixed parameters get an identity row so they stay put
+                row[k] = if free[k] { row[k] * (1.0 + lambda) + 1e-12 } else { 1.0 };
+            }
+            let v: [f64; N_PARAMS] = std::array::from_fn(|k| if free[k] { jtr[k] } else { 0.0 });
+            let Some(delta) = solve(m, v) else {
+                lambda *= 10.0;
+                continue;
+            };
+
+            let mut trial = p;
+            for k in 0..N_PARAMS {
+                trial[k] += delta[k];
+            }
+            trial[4] = trial[4].max(0.0);
+            let tr = residuals(&trial);
+            let tc = cost(&tr);
+            if tc.is_finite() && tc < c {
+                let gain = (c - tc) / c.max(f64::MIN_POSITIVE);
+                p = trial;
+                r = tr;
+                c = tc;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = gain > 1e-12;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let mut result = unpack(&p);
+    if !(result.qr.is_finite() && result.qc.is_finite() && result.f0.is_finite()) {
+        return Err("Fit diverged.".to_string());
+    }
+    result.qi = 1.0 / (1.0 / result.qr - result.phi.cos() / result.qc);
+    result.phase = (result.phase + PI).rem_euclid(2.0 * PI) - PI;
+    result.residual = (c / n as f64).sqrt();
+    Ok(result)
+}
+
+// Indices of the trace within +/- half_window of freq
+fn window(trace: &SweepTrace, freq: f64, half_window: f64) -> std::ops::Range<usize> {
+    let lo = trace.freqs.partition_point(|f| *f < freq - half_window);
+    let hi = trace.freqs.partition_point(|f| *f <= freq + half_window);
+    lo..hi
+}
+
+// A fit of one detected resonance
+pub struct ResonatorFit {
+    pub id: Option<u32>,                      // Resonator ID for fits of targeted sweep windows
+    pub guess: f64,                           // Detected frequency the fit window is centred on (Hz)
+    pub freqs: Vec<f64>,                      // Frequencies in the fit window (Hz)
+    pub iq: Vec<Complex<f64>>,                // Data in the fit window
+    pub result: Result<FitResult, String>,    // Fit result or failure reason
+}
+
+// Resonator fitting pane state
+pub struct ResonatorFitter {
+    pub trace_index: usize,       // Power setting to fit
+    pub fit_window: String,       // Full width of the window around each resonance (Hz)
+    pub fit_nonlinear: bool,      // Fit the nonlinearity parameter
+    pub fits: Vec<ResonatorFit>,  // Last fit results
+    pub selected: usize,          // Fit shown in the plots
+    pub message: Option<String>,  // Error message
+}
+
+impl Default for ResonatorFitter {
+    fn default() -> Self {
+        Self {
+            trace_index: 0,
+            fit_window: "200000".to_string(),
+            fit_nonlinear: true,
+            fits: Vec::new(),
+            selected: 0,
+            message: None,
+        }
+    }
+}
+
+impl ResonatorFitter {
+    // Fit every detected resonance in the given trace
+    pub fn fit_all(&mut self, trace: &SweepTrace, found: &[FoundResonator]) -> Result<(), String> {
+        let width = self
+            .fit_window
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|w| *w > 0.0)
+            .ok_or("Invalid fit window.")?;
+
+        self.fits = found
+            .iter()
+            .map(|res| {
+                let range = window(trace, res.freq, width / 2.0);
+                let freqs = trace.freqs[range.clone()].to_vec();
+                let iq = trace.iq[range].to_vec();
+                let result = fit_resonator(&freqs, &iq, self.fit_nonlinear);
+                ResonatorFit { id: None, guess: res.freq, freqs, iq, result }
+            })
+            .collect();
+        self.selected = 0;
+        Ok(())
+    }
+
+    // Fit every window of a targeted sweep at the given power setting
+    pub fn fit_targeted(&mut self, sweeps: &[ResonatorSweep], trace_index: usize) {
+        self.fits = sweeps
+            .iter()
+            .filter_map(|s| {
+                let trace = s.traces.get(trace_index)?;
+                let result = fit_resonator(&trace.freqs, &trace.iq, self.fit_nonlinear);
+                Some(ResonatorFit {
+                    id: s.id,
+                    guess: s.center,
+                    freqs: trace.freqs.clone(),
+                    iq: trace.iq.clone(),
+                    result,
+                })
+            })
+            .collect();
+        self.selected = 0;
+    }
+
+    // Draw the fitting pane
+    pub fn show(&mut self, ui: &mut egui::Ui, traces: &[SweepTrace], found: &[FoundResonator], targeted: &[ResonatorSweep]) {
+        if traces.is_empty() {
+            ui.label("No sweep available. Perform a sweep first.");
+            return;
+        }
+        self.trace_index = self.trace_index.min(traces.len() - 1);
+
+        let label = |t: &SweepTrace| format!("In {} dB / Out {} dB / Scale {}", t.setting.attens.input, t.setting.attens.output, t.setting.fft_scale);
+        egui::ComboBox::from_label("Power Setting")
+            .selected_text(label(&traces[self.trace_index]))
+            .show_ui(ui, |ui| {
+                for (i, t) in traces.iter().enumerate() {
+                    ui.selectable_value(&mut self.trace_index, i, label(t));
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Fit Window (Hz):");
+            ui.text_edit_singleline(&mut self.fit_window);
+            ui.checkbox(&mut self.fit_nonlinear, "Fit Nonlinearity");
+        });
+
+        ui.label(format!("Detected resonators: {} (use the Resonator Finder pane to detect them)", found.len()));
+        if ui.button("Fit Detected Resonators").clicked() {
+            match self.fit_all(&traces[self.trace_index], found) {
+                Ok(()) => self.message = None,
+                Err(e) => self.message = Some(e),
+            }
+        }
+
+        // Targeted sweeps are already split into one window per resonator
+        if !targeted.is_empty() && ui.button(format!("Fit Targeted Sweep Windows ({})", targeted.len())).clicked() {
+            self.fit_targeted(targeted, self.trace_index);
+            self.message = None;
+        }
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        if self.fits.is_empty() {
+            return;
+        }
+        self.selected = self.selected.min(self.fits.len() - 1);
+
+        // Results table, click a row to plot it
+        egui::ScrollArea::vertical().id_salt("fit_results").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("fit_results_grid").striped(true).show(ui, |ui| {
+                for heading in ["", "ID", "f0 (MHz)", "Qr", "Qc", "Qi", "a_nl", "Residual"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                for (i, fit) in self.fits.iter().enumerate() {
+                    ui.radio_value(&mut self.selected, i, "");
+                    ui.label(fit.id.map_or("-".to_string(), |id| id.to_string()));
+                    match &fit.result {
+                        Ok(r) => {
+                            ui.label(format!("{:.6}", r.f0 / 1e6));
+                            ui.label(format!("{:.0}", r.qr));
+                            ui.label(format!("{:.0}", r.qc));
+                            ui.label(format!("{:.0}", r.qi));
+                            ui.label(format!("{:.3}", r.a_nl));
+                            ui.label(format!("{:.2e}", r.residual));
+                        }
+                        Err(e) => {
+                            ui.label(format!("{:.6}", fit.guess / 1e6));
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        // Magnitude and IQ plots of the selected fit
+        let fit = &self.fits[self.selected.min(self.fits.len() - 1)];
+        let data_mag: Vec<[f64; 2]> = fit.freqs.iter().zip(&fit.iq).map(|(f, z)| [f / 1e6, z.norm()]).collect();
+        let data_iq: Vec<[f64; 2]> = fit.iq.iter().map(|z| [z.re, z.im]).collect();
+        let model = fit.result.as_ref().ok().map(|r| {
+            // Evaluate the model on a fine grid for smooth curves
+            let (lo, hi) = (fit.freqs[0], fit.freqs[fit.freqs.len() - 1]);
+            let fine: Vec<f64> = (0..500).map(|i| lo + (hi - lo) * i as f64 / 499.0).collect();
+            let s = r.model(&fine);
+            let mag: Vec<[f64; 2]> = fine.iter().zip(&s).map(|(f, z)| [f / 1e6, z.norm()]).collect();
+            let iq: Vec<[f64; 2]> = s.iter().map(|z| [z.re, z.im]).collect();
+            (mag, iq)
+        });
+
+        ui.columns(2, |columns| {
+            Plot::new("fit_magnitude_plot")
+                .height(300.0)
+                .x_axis_label("Frequency (MHz)")
+                .y_axis_label("|S21|")
+                .show(&mut columns[0], |plot_ui| {
+                    plot_ui.points(Points::new(data_mag).radius(2.0).name("Data"));
+                    if let Some((mag, _)) = &model {
+                        plot_ui.line(Line::new(mag.clone()).name("Fit"));
+                    }
+                });
+            Plot::new("fit_iq_plot")
+                .height(300.0)
+                .data_aspect(1.0)
+                .x_axis_label("I")
+                .y_axis_label("Q")
+                .show(&mut columns[1], |plot_ui| {
+                    plot_ui.points(Points::new(data_iq).radius(2.0).name("Data"));
+                    if let Some((_, iq)) = &model {
+                        plot_ui.line(Line::new(iq.clone()).name("Fit"));
+                    }
+                });
+        });
+    }
+}
@@ -0,0 +1,297 @@
+// Resonator list
+// The table of our resonators: edited by hand, imported/exported as CSV/JSON and populated by sweep analysis
+// Called to in gui
+
+use crate::dac_comb::Tone;
+use crate::iq_loop::LoopCal;
+use crate::power_map::ReadoutPower;
+use crate::resonator_finder::FoundResonator;
+use crate::resonator_fit::ResonatorFit;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+// A single resonator
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Resonator {
+    pub id: u32,
+    pub freq: f64,          // Resonance / tone frequency (Hz)
+    pub readout_atten: f64, // Readout power as output attenuation (dB)
+    pub phase_offset: f64,  // Tone phase offset (rad)
+    pub enabled: bool,      // Include in the DAC comb and targeted sweeps
+    #[serde(default)]
+    pub loop_cal: Option<LoopCal>, // IQ loop centre and rotation for phase readout
+    pub notes: String,
+}
+
+// The resonator list and the pane state used to edit it
+pub struct ResonatorList {
+    pub resonators: Vec<Resonator>,
+    pub path: String,            // File for import/export (.csv or .json)
+    pub match_window: String,    // Fits and readout powers update the nearest resonator within this window (Hz)
+    pub message: Option<String>, // Error or status message
+}
+
+impl Default for ResonatorList {
+    fn default() -> Self {
+        Self {
+            resonators: Vec::new(),
+            path: "resonators.csv".to_string(),
+            match_window: "100000".to_string(),
+            message: None,
+        }
+    }
+}
+
+impl ResonatorList {
+    fn next_id(&self) -> u32 {
+        self.resonators.iter().map(|r| r.id + 1).max().unwrap_or(0)
+    }
+
+    // Add a resonator with default settings
+    pub fn add(&mut self, freq: f64) {
+        let id = self.next_id();
+        self.resonators.push(Resonator {
+            id,
+            freq,
+            readout_atten: 0.0,
+            phase_offset: 0.0,
+            enabled: true,
+            loop_cal: None,
+            notes: String::new(),
+        });
+    }
+
+    // Enabled resonators
+    pub fn enabled(&self) -> impl Iterator<Item = &Resonator> {
+        self.resonators.iter().filter(|r| r.enabled)
+    }
+
+    // Tones for the DAC comb; amplitudes follow the readout attenuations relative to the least attenuated tone
+    pub fn tones(&self) -> Vec<Tone> {
+        let min_atten = self.enabled().map(|r| r.readout_atten).fold(f64::INFINITY, f64::min);
+        self.enabled()
+            .map(|r| Tone {
+                freq: r.freq,
+                amplitude: 10f64.powf(-(r.readout_atten - min_atten) / 20.0),
+                phase: r.phase_offset,
+                id: Some(r.id),
+            })
+            .collect()
+    }
+
+    fn window(&self) -> f64 {
+        self.match_window.trim().parse::<f64>().unwrap_or(0.0)
+    }
+
+    // Index of the resonator nearest to freq within the match window
+    pub fn nearest(&self, freq: f64) -> Option<usize> {
+        let window = self.window();
+        self.resonators
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (i, (r.freq - freq).abs()))
+            .filter(|(_, d)| *d <= window)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Add every detected resonance that is not already in the list
+    pub fn add_found(&mut self, found: &[FoundResonator]) -> usize {
+        let mut added = 0;
+        for f in found {
+            if self.nearest(f.freq).is_none() {
+                self.add(f.freq);
+                added += 1;
+            }
+        }
+        self.resonators.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+        added
+    }
+
+    // Move resonators to their fitted f0
+    pub fn apply_fits(&mut self, fits: &[ResonatorFit]) -> usize {
+        let mut updated = 0;
+        for fit in fits {
+            if let (Ok(result), Some(i)) = (&fit.result, self.nearest(fit.guess)) {
+                self.resonators[i].freq = result.f0;
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    // Store readout powers picked on the power sweep map
+    pub fn apply_readout_powers(&mut self, picks: &[ReadoutPower]) -> usize {
+        let mut updated = 0;
+        for pick in picks {
+            if let Some(i) = self.nearest(pick.freq) {
+                self.resonators[i].readout_atten = pick.attens.output as f64;
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    // Write the list as CSV (notes last so they may contain commas, empty loop columns when uncalibrated)
+    pub fn export_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        writeln!(file, "id,freq_hz,readout_atten_db,phase_offset_rad,enabled,loop_center_i,loop_center_q,loop_radius,loop_rotation_rad,notes")?;
+        for r in &self.resonators {
+            let cal = r.loop_cal.map_or(",,,".to_string(), |c| format!("{},{},{},{}", c.center_i, c.center_q, c.radius, c.rotation));
+            writeln!(file, "{},{},{},{},{},{},{}", r.id, r.freq, r.readout_atten, r.phase_offset, r.enabled, cal, r.notes)?;
+        }
+        Ok(())
+    }
+
+    // Read a list written by export_csv (lists without the loop columns are still accepted)
+    pub fn import_csv(path: &Path) -> Result<Vec<Resonator>, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let has_cal = text.lines().next().is_some_and(|header| header.contains("loop_center_i"));
+        let columns = if has_cal { 10 } else { 6 };
+        text.lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                let fields: Vec<&str> = line.splitn(columns, ',').map(str::trim).collect();
+                let bad = || format!("Invalid resonator on line {}.", i + 2);
+                if fields.len() < columns - 1 {
+                    return Err(bad());
+                }
+                let loop_cal = if has_cal && !fields[5].is_empty() {
+                    Some(LoopCal {
+                        center_i: fields[5].parse().map_err(|_| bad())?,
+                        center_q: fields[6].parse().map_err(|_| bad())?,
+                        radius: fields[7].parse().map_err(|_| bad())?,
+                        rotation: fields[8].parse().map_err(|_| bad())?,
+                    })
+                } else {
+                    None
+                };
+                Ok(Resonator {
+                    id: fields[0].parse().map_err(|_| bad())?,
+                    freq: fields[1].parse().map_err(|_| bad())?,
+                    readout_atten: fields[2].parse().map_err(|_| bad())?,
+                    phase_offset: fields[3].parse().map_err(|_| bad())?,
+                    enabled: fields[4].parse().map_err(|_| bad())?,
+                    loop_cal,
+                    notes: fields.get(columns - 1).unwrap_or(&"").to_string(),
+                })
+            })
+            .collect()
+    }
+
+    // Import or export based on the file extension
+    fn import(&mut self) -> Result<String, String> {
+        let path = Path::new(self.path.trim());
+        let resonators: Vec<Resonator> = if is_json(path) {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+        } else {
+            Self::import_csv(path)?
+        };
+        // Retuning, drift tracking and loop calibrations all look resonators up by ID
+        let mut ids = std::collections::BTreeSet::new();
+        if let Some(r) = resonators.iter().find(|r| !ids.insert(r.id)) {
+            return Err(format!("{} lists resonator ID {} more than once.", path.display(), r.id));
+        }
+        self.resonators = resonators;
+        Ok(format!("Imported {} resonators from {}", self.resonators.len(), path.display()))
+    }
+
+    fn export(&self) -> Result<String, String> {
+        let path = Path::new(self.path.trim());
+        let result = if is_json(path) {
+            serde_json::to_string_pretty(&self.resonators)
+                .map_err(std::io::Error::from)
+                .and_then(|json| std::fs::write(path, json))
+        } else {
+            self.export_csv(path)
+        };
+        result.map_err(|e| format!("Failed to export resonators: {}", e))?;
+        Ok(format!("Exported {} resonators to {}", self.resonators.len(), path.display()))
+    }
+
+    // Draw the resonator table pane
+    pub fn show(&mut self, ui: &mut egui::Ui, found: &[FoundResonator], fits: &[ResonatorFit], picks: &[ReadoutPower]) {
+        // File import/export
+        ui.horizontal(|ui| {
+            ui.label("File (.csv or .json):");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Import").clicked() {
+                self.message = Some(self.import().unwrap_or_else(|e| e));
+            }
+            if ui.button("Export").clicked() {
+                self.message = Some(self.export().unwrap_or_else(|e| e));
+            }
+        });
+
+        // Populate from sweep analysis
+        ui.horizontal(|ui| {
+            ui.label("Match Window (Hz):");
+            ui.text_edit_singleline(&mut self.match_window);
+        });
+        ui.horizontal(|ui| {
+            if ui.button(format!("Add Detected ({})", found.len())).clicked() {
+                let added = self.add_found(found);
+                self.message = Some(format!("Added {} resonators.", added));
+            }
+            if ui.button(format!("Apply Fitted f0 ({})", fits.len())).clicked() {
+                let updated = self.apply_fits(fits);
+                self.message = Some(format!("Updated {} resonator frequencies.", updated));
+            }
+            if ui.button(format!("Apply Readout Powers ({})", picks.len())).clicked() {
+                let updated = self.apply_readout_powers(picks);
+                self.message = Some(format!("Updated {} readout powers.", updated));
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} resonators, {} enabled", self.resonators.len(), self.enabled().count()));
+            if ui.button("Add Resonator").clicked() {
+                let freq = self.resonators.last().map_or(0.0, |r| r.freq);
+                self.add(freq);
+            }
+            if ui.button("Sort by Frequency").clicked() {
+                self.resonators.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+            }
+        });
+
+        // Editable table
+        let mut remove = None;
+        egui::ScrollArea::vertical().id_salt("resonator_table").show(ui, |ui| {
+            egui::Grid::new("resonator_table_grid").striped(true).show(ui, |ui| {
+                for heading in ["ID", "Frequency (Hz)", "Readout Atten (dB)", "Phase Offset (rad)", "Enabled", "Notes", ""] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                for (i, r) in self.resonators.iter_mut().enumerate() {
+                    ui.label(r.id.to_string());
+                    ui.add(egui::DragValue::new(&mut r.freq).speed(1000.0).max_decimals(1));
+                    ui.add(egui::DragValue::new(&mut r.readout_atten).speed(0.25).range(0.0..=63.75));
+                    ui.add(egui::DragValue::new(&mut r.phase_offset).speed(0.01).range(-std::f64::consts::PI..=std::f64::consts::PI));
+                    ui.checkbox(&mut r.enabled, "");
+                    ui.text_edit_singleline(&mut r.notes);
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        if let Some(i) = remove {
+            self.resonators.remove(i);
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
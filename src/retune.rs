@@ -0,0 +1,228 @@
+// DAC comb retuning
+// Moves the tones of the comb onto freshly fitted resonance frequencies and keeps the previous table for undo.
+// The resonator list only changes once the board has applied the new table.
+// Called to in gui
+
+use crate::dac_comb::{self, table_hash, DacComb, DacTable};
+use crate::resonator_fit::ResonatorFit;
+use crate::resonators::ResonatorList;
+use eframe::egui;
+use std::collections::BTreeMap;
+
+// Where the fresh f0 values come from
+#[derive(PartialEq, Clone, Copy)]
+pub enum F0Source {
+    Fits,  // Resonator fitting pane
+    Drift, // Latest drift tracking sweep
+}
+
+// Tone frequency of one resonator before and after retuning
+pub struct ToneChange {
+    pub id: u32,
+    pub before: f64, // Hz
+    pub after: f64,  // Hz
+}
+
+// What was loaded before the last retune
+struct Previous {
+    comb: Option<DacComb>,
+    table: DacTable,
+    freqs: Vec<(u32, f64)>, // Resonator list frequencies by ID
+}
+
+// Upload sent to the worker and not yet applied
+struct Pending {
+    hash: u64,                  // table_hash of the uploaded table
+    comb: Option<DacComb>,      // Comb to adopt once applied
+    freqs: Vec<(u32, f64)>,     // Resonator list frequencies to adopt once applied
+    previous: Option<Previous>, // Undo state once applied; None for an undo
+    changes: Vec<ToneChange>,
+}
+
+// Requested by the retune pane, carried out by the gui
+pub enum RetuneAction {
+    Retune,
+    Undo,
+}
+
+pub struct Retuner {
+    pub source: F0Source,
+    pub changes: Vec<ToneChange>, // Diff of the last retune
+    pub message: Option<String>,  // Error or status message
+    previous: Option<Previous>,
+    pending: Option<Pending>,
+}
+
+impl Default for Retuner {
+    fn default() -> Self {
+        Self {
+            source: F0Source::Fits,
+            changes: Vec::new(),
+            message: None,
+            previous: None,
+            pending: None,
+        }
+    }
+}
+
+// Fitted f0 per resonator ID; fits without an ID are matched to the nearest resonator
+pub fn fitted_f0s(list: &ResonatorList, fits: &[ResonatorFit]) -> BTreeMap<u32, f64> {
+    fits.iter()
+        .filter_map(|fit| {
+            let f0 = fit.result.as_ref().ok()?.f0;
+            let id = fit.id.or_else(|| list.nearest(fit.guess).map(|i| list.resonators[i].id))?;
+            Some((id, f0))
+        })
+        .collect()
+}
+
+impl Retuner {
+    // Regenerate the comb with the resonators moved to f0s and return its table for upload. The current comb
+    // (or the table last read from the board) is kept for undo; the list is updated by applied().
+    pub fn retune(
+        &mut self,
+        list: &ResonatorList,
+        f0s: &BTreeMap<u32, f64>,
+        current_comb: Option<&DacComb>,
+        current_table: Option<&DacTable>,
+        lo: f64,
+        fill: f64,
+    ) -> Result<DacTable, String> {
+        if self.pending.is_some() {
+            return Err("Waiting for the board to apply the last DAC table.".to_string());
+        }
+        // Prefer the comb we generated, else whatever was last read from the board
+        let Some(table) = current_comb.map(|c| c.table.clone()).or_else(|| current_table.cloned()) else {
+            return Err("Generate or read the current DAC table first so the retune can be undone.".to_string());
+        };
+        if f0s.is_empty() {
+            return Err("No fitted f0 values to retune to.".to_string());
+        }
+
+        let mut retuned = list.resonators.clone();
+        let mut changes = Vec::new();
+        for r in retuned.iter_mut().filter(|r| r.enabled) {
+            if let Some(&f0) = f0s.get(&r.id) {
+                changes.push(ToneChange { id: r.id, before: r.freq, after: f0 });
+                r.freq = f0;
+            }
+        }
+        if changes.is_empty() {
+            return Err("None of the fitted f0 values belong to an enabled resonator.".to_string());
+        }
+
+        let retuned = ResonatorList { resonators: retuned, ..Default::default() };
+        let comb = dac_comb::generate(&retuned.tones(), lo, fill)?;
+
+        let upload = comb.table.clone();
+        self.pending = Some(Pending {
+            hash: table_hash(&upload[..]),
+            comb: Some(comb),
+            freqs: retuned.resonators.iter().map(|r| (r.id, r.freq)).collect(),
+            previous: Some(Previous {
+                comb: current_comb.cloned(),
+                table,
+                freqs: list.resonators.iter().map(|r| (r.id, r.freq)).collect(),
+            }),
+            changes,
+        });
+        Ok(upload)
+    }
+
+    // Return the previous table for upload; the list is restored by applied()
+    pub fn undo(&mut self) -> Option<DacTable> {
+        if self.pending.is_some() {
+            return None;
+        }
+        let previous = self.previous.as_ref()?;
+        self.pending = Some(Pending {
+            hash: table_hash(&previous.table[..]),
+            comb: previous.comb.clone(),
+            freqs: previous.freqs.clone(),
+            previous: None,
+            changes: Vec::new(),
+        });
+        Some(previous.table.clone())
+    }
+
+    // The worker applied a DAC table. If it is the pending one the resonator list is updated and the comb now
+    // on the board is returned; any other table abandons the pending retune or undo.
+    pub fn applied(&mut self, list: &mut ResonatorList, hash: u64) -> Option<Option<DacComb>> {
+        let Some(pending) = self.pending.take() else {
+            // Another table replaced the retuned one, undoing the retune would no longer restore the board
+            if self.previous.take().is_some() {
+                self.message = Some("Another DAC table was applied; retune undo is no longer available.".to_string());
+            }
+            return None;
+        };
+        if pending.hash != hash {
+            self.message = Some("A different DAC table was applied; the resonator list was left unchanged.".to_string());
+            return None;
+        }
+        for r in list.resonators.iter_mut() {
+            if let Some(&(_, freq)) = pending.freqs.iter().find(|(id, _)| *id == r.id) {
+                r.freq = freq;
+            }
+        }
+        self.message = Some(match pending.previous {
+            Some(_) => format!("Retuned {} tones.", pending.changes.len()),
+            None => "Restored the previous DAC table.".to_string(),
+        });
+        self.previous = pending.previous;
+        self.changes = pending.changes;
+        Some(pending.comb)
+    }
+
+    // The worker failed to apply the table with this hash, or None if the upload could not be sent
+    pub fn failed(&mut self, hash: Option<u64>) {
+        if self.pending.as_ref().is_some_and(|p| hash.is_none_or(|h| h == p.hash)) {
+            self.pending = None;
+            self.message = Some("The board did not take the DAC table; the resonator list was left unchanged.".to_string());
+        }
+    }
+
+    // Draw the retune controls and the before/after diff
+    pub fn show(&mut self, ui: &mut egui::Ui, fit_count: usize, drift_count: usize) -> Option<RetuneAction> {
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Fitted f0 from:");
+            ui.radio_value(&mut self.source, F0Source::Fits, format!("Resonator Fits ({})", fit_count));
+            ui.radio_value(&mut self.source, F0Source::Drift, format!("Drift Tracking ({})", drift_count));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Retune").clicked() {
+                action = Some(RetuneAction::Retune);
+            }
+            let idle = self.pending.is_none();
+            if ui.add_enabled(self.previous.is_some() && idle, egui::Button::new("Undo Retune")).clicked() {
+                action = Some(RetuneAction::Undo);
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        if !self.changes.is_empty() {
+            egui::ScrollArea::vertical().id_salt("retune_diff").max_height(200.0).show(ui, |ui| {
+                egui::Grid::new("retune_diff_grid").striped(true).show(ui, |ui| {
+                    for heading in ["ID", "Before (Hz)", "After (Hz)", "Shift (kHz)"] {
+                        ui.label(heading);
+                    }
+                    ui.end_row();
+
+                    for change in &self.changes {
+                        ui.label(change.id.to_string());
+                        ui.label(format!("{:.1}", change.before));
+                        ui.label(format!("{:.1}", change.after));
+                        ui.label(format!("{:+.3}", (change.after - change.before) / 1e3));
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+
+        action
+    }
+}
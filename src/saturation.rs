@@ -0,0 +1,162 @@
+// ADC saturation and DSP overflow monitor
+// Checks every capture for samples at the i16 limits and suggests an attenuation or FFT scale change when it clips
+// Called to in gui
+
+use crate::gui::VALID_FFT_SCALES;
+use eframe::egui;
+use num::Complex;
+
+// Samples with either component at full scale (+32767 or -32768) count as clipped
+const CLIP_LEVEL: i16 = i16::MAX;
+
+// Where the analysed samples came from
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stage {
+    Adc, // Raw IQ: clipping happens at the ADC
+    Dsp, // Channelized IQ: clipping is FFT/DDC overflow
+}
+
+// Saturation figures of one capture
+pub struct SaturationReport {
+    pub stage: Stage,
+    pub samples: usize,
+    pub clipped: usize,
+    pub peak: i16,     // Largest |I| or |Q|
+    pub peak_dbfs: f64, // Peak relative to full scale
+}
+
+impl SaturationReport {
+    pub fn fraction(&self) -> f64 {
+        self.clipped as f64 / self.samples.max(1) as f64
+    }
+}
+
+// Analyse a block of IQ samples
+pub fn analyse<'a>(stage: Stage, data: impl IntoIterator<Item = &'a Complex<i16>>) -> SaturationReport {
+    let mut samples = 0;
+    let mut clipped = 0;
+    let mut peak: i16 = 0;
+    for z in data {
+        // saturating_abs maps -32768 onto 32767
+        let m = z.re.saturating_abs().max(z.im.saturating_abs());
+        samples += 1;
+        if m == CLIP_LEVEL {
+            clipped += 1;
+        }
+        peak = peak.max(m);
+    }
+    SaturationReport {
+        stage,
+        samples,
+        clipped,
+        peak,
+        peak_dbfs: 20.0 * (peak.max(1) as f64 / CLIP_LEVEL as f64).log10(),
+    }
+}
+
+pub struct SaturationMonitor {
+    pub threshold: String,              // Clipped fraction that raises a warning
+    pub adc: Option<SaturationReport>,  // Last raw IQ capture
+    pub dsp: Option<SaturationReport>,  // Last channelized capture
+    pub clipped_captures: u64,          // Captures over the threshold since the last reset
+    pub captures: u64,
+    adc_warning: Option<String>,        // Warning with the suggested change, per stage
+    dsp_warning: Option<String>,
+}
+
+impl Default for SaturationMonitor {
+    fn default() -> Self {
+        Self {
+            threshold: "0".to_string(),
+            adc: None,
+            dsp: None,
+            clipped_captures: 0,
+            captures: 0,
+            adc_warning: None,
+            dsp_warning: None,
+        }
+    }
+}
+
+impl SaturationMonitor {
+    // Record a capture's report; input_atten and fft_scale are the board's current settings, used for the suggestion
+    pub fn record(&mut self, report: SaturationReport, input_atten: Option<f32>, fft_scale: Option<u16>) {
+        let threshold = self.threshold.trim().parse::<f64>().unwrap_or(0.0);
+        self.captures += 1;
+
+        let clipping = report.clipped > 0 && report.fraction() > threshold;
+        if clipping {
+            self.clipped_captures += 1;
+        }
+
+        let percent = 100.0 * report.fraction();
+        match report.stage {
+            Stage::Adc => {
+                self.adc_warning = clipping.then(|| {
+                    // Clipped samples hide the true peak, so step harder the more samples clip
+                    let step = if report.fraction() > 0.01 { 6.0 } else { 3.0 };
+                    let suggestion = match input_atten {
+                        Some(a) => format!("raise the IF input attenuation from {} dB to {} dB", a, (a + step).min(63.75)),
+                        None => format!("raise the IF input attenuation by {} dB", step),
+                    };
+                    format!("ADC clipping: {:.3}% of samples at full scale; {}.", percent, suggestion)
+                });
+                self.adc = Some(report);
+            }
+            Stage::Dsp => {
+                self.dsp_warning = clipping.then(|| {
+                    // Scales with more shift stages set divide the FFT output down further
+                    let suggestion = fft_scale
+                        .and_then(|scale| {
+                            VALID_FFT_SCALES
+                                .iter()
+                                .filter(|s| s.count_ones() > scale.count_ones())
+                                .min_by_key(|s| s.count_ones())
+                        })
+                        .map_or("increase the DSP scale (FFT shift) or the IF output attenuation".to_string(), |s| {
+                            format!("set the DSP scale to {} or raise the IF output attenuation", s)
+                        });
+                    format!("DSP overflow: {:.3}% of channel samples at full scale; {}.", percent, suggestion)
+                });
+                self.dsp = Some(report);
+            }
+        }
+    }
+
+    // Current warning for the status bar, ADC clipping first
+    pub fn warning(&self) -> Option<&String> {
+        self.adc_warning.as_ref().or(self.dsp_warning.as_ref())
+    }
+
+    // Draw the monitor details
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Warn above clipped fraction:");
+            ui.text_edit_singleline(&mut self.threshold);
+            if ui.button("Reset Counters").clicked() {
+                self.clipped_captures = 0;
+                self.captures = 0;
+            }
+        });
+        ui.label(format!("{} of {} captures clipped", self.clipped_captures, self.captures));
+
+        for (name, report) in [("Raw IQ (ADC)", &self.adc), ("Channelized (DSP)", &self.dsp)] {
+            match report {
+                Some(r) => ui.label(format!(
+                    "{}: peak {} ({:.1} dBFS), {} of {} samples clipped ({:.3}%)",
+                    name,
+                    r.peak,
+                    r.peak_dbfs,
+                    r.clipped,
+                    r.samples,
+                    100.0 * r.fraction()
+                )),
+                None => ui.label(format!("{}: no captures yet", name)),
+            };
+        }
+
+        for warning in [&self.adc_warning, &self.dsp_warning].into_iter().flatten() {
+            ui.colored_label(egui::Color32::RED, warning);
+        }
+    }
+}
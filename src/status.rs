@@ -14,8 +14,7 @@ impl Status {
         }
     }
 
-    // Method for updating status (will be filled in)
-    #[allow(dead_code)]
+    // Method for updating status
     pub fn update(&mut self, message: &str) {
         self.status_message = message.to_string();
     }
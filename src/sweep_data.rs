@@ -0,0 +1,90 @@
+// Sweep data helpers
+// Flattens a gen3_rpc Sweep into one trace per power setting so the panes can plot and analyse it
+// Called to in gui
+
+use gen3_rpc::utils::client::{PowerSetting, Sweep};
+use gen3_rpc::Hertz;
+use num::Complex;
+
+// The sweep response at a single power setting
+#[derive(Clone)]
+pub struct SweepTrace {
+    pub setting: PowerSetting,  // Power setting the trace was taken at
+    pub freqs: Vec<f64>,        // Sweep frequencies (Hz)
+    pub iq: Vec<Complex<f64>>,  // Averaged IQ at each frequency
+}
+
+impl SweepTrace {
+    // |S21| in dB at each frequency
+    pub fn magnitude_db(&self) -> Vec<f64> {
+        self.iq.iter().map(|z| 20.0 * z.norm().max(f64::MIN_POSITIVE).log10()).collect()
+    }
+
+    // IQ velocity |dIQ/df| at each frequency (central differences, one-sided at the ends)
+    pub fn iq_velocity(&self) -> Vec<f64> {
+        let n = self.iq.len();
+        if n < 2 {
+            return vec![0.0; n];
+        }
+        (0..n)
+            .map(|i| {
+                let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+                let df = self.freqs[b] - self.freqs[a];
+                if df == 0.0 {
+                    0.0
+                } else {
+                    (self.iq[b] - self.iq[a]).norm() / df.abs()
+                }
+            })
+            .collect()
+    }
+}
+
+// Convert a rational Hertz value to floating point Hz
+pub fn hertz_to_f64(freq: &Hertz) -> f64 {
+    *freq.numer() as f64 / *freq.denom() as f64
+}
+
+// One line describing a sweep: points, span and power settings
+pub fn summary(traces: &[SweepTrace]) -> String {
+    let Some(trace) = traces.first() else {
+        return "Sweep returned no data.".to_string();
+    };
+    let (lo, hi) = trace.freqs.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), f| (lo.min(*f), hi.max(*f)));
+    let inputs = traces.iter().map(|t| t.setting.attens.input);
+    let (min_in, max_in) = inputs.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), a| (lo.min(a), hi.max(a)));
+    format!(
+        "Sweep: {} points from {:.6} to {:.6} MHz at {} power settings (input attenuation {} to {} dB).",
+        trace.freqs.len(),
+        lo / 1e6,
+        hi / 1e6,
+        traces.len(),
+        min_in,
+        max_in
+    )
+}
+
+// Split a Sweep into one trace per power setting, averaging the IQ captured at each frequency
+pub fn traces(sweep: &Sweep) -> Vec<SweepTrace> {
+    let freqs: Vec<f64> = sweep.config.freqs.iter().map(hertz_to_f64).collect();
+
+    sweep
+        .sweep_results
+        .iter()
+        .map(|result| SweepTrace {
+            setting: result.setting,
+            freqs: freqs.clone(),
+            iq: result
+                .iq
+                .iter()
+                .map(|samples| {
+                    if samples.is_empty() {
+                        Complex::new(0.0, 0.0)
+                    } else {
+                        samples.iter().sum::<Complex<f64>>() / samples.len() as f64
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+// Targeted sweeps
+// Narrow frequency windows centred on known resonators, swept as one SweepConfig and split back per resonator
+// Called to in gui
+
+use crate::freq_list;
+use crate::resonators::ResonatorList;
+use crate::sweep_data::SweepTrace;
+use gen3_rpc::Hertz;
+
+// The windows of a targeted sweep
+#[derive(Clone)]
+pub struct TargetedSweep {
+    pub windows: Vec<(Option<u32>, f64)>, // Resonator ID (if from the resonator list) and window centre (Hz)
+    pub span: f64,                        // Full width of each window (Hz)
+    pub points: usize,                    // Points per window
+}
+
+// The sweep data of one resonator's window
+pub struct ResonatorSweep {
+    pub id: Option<u32>,
+    pub center: f64,
+    pub traces: Vec<SweepTrace>, // One per power setting
+}
+
+impl TargetedSweep {
+    // Windows around every enabled resonator in the list
+    pub fn from_resonators(list: &ResonatorList, span: f64, points: usize) -> Self {
+        Self {
+            windows: list.enabled().map(|r| (Some(r.id), r.freq)).collect(),
+            span,
+            points,
+        }
+    }
+
+    // The merged frequency list for SweepConfig.freqs
+    pub fn freqs(&self) -> Result<Vec<Hertz>, String> {
+        let centers: Vec<f64> = self.windows.iter().map(|(_, c)| *c).collect();
+        Ok(freq_list::windows(&centers, self.span, self.points)?
+            .into_iter()
+            .map(freq_list::to_hertz)
+            .collect())
+    }
+
+    // Split the traces of the returned sweep into one result per window
+    pub fn split(&self, traces: &[SweepTrace]) -> Vec<ResonatorSweep> {
+        self.windows
+            .iter()
+            .map(|&(id, center)| ResonatorSweep {
+                id,
+                center,
+                traces: traces
+                    .iter()
+                    .map(|t| {
+                        // Integer-Hz rounding can move the window edges by up to half a Hz
+                        let lo = t.freqs.partition_point(|f| *f < center - self.span / 2.0 - 0.5);
+                        let hi = t.freqs.partition_point(|f| *f <= center + self.span / 2.0 + 0.5);
+                        SweepTrace {
+                            setting: t.setting,
+                            freqs: t.freqs[lo..hi].to_vec(),
+                            iq: t.iq[lo..hi].to_vec(),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
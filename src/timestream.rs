@@ -0,0 +1,234 @@
+// Phase timestreams
+// Captures channelized IQ or firmware phase for selected channels, applies the IQ loop calibrations and keeps a rolling history to plot
+// Called to in gui
+
+use crate::iq_loop::LoopCal;
+use crate::worker::{ChannelTap, RPCCommand};
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot};
+use gen3_rpc::Snap;
+use num::Complex;
+use std::collections::{BTreeMap, VecDeque};
+use std::f64::consts::PI;
+
+// Most points drawn per channel in the timestream plot
+const MAX_PLOT_POINTS: usize = 4096;
+
+// One channel of the last capture
+pub struct ChannelSamples {
+    pub channel: usize,
+    pub phase: Vec<f64>,               // Phase (rad)
+    pub dissipation: Option<Vec<f64>>, // Dissipation, for loop calibrated DDC IQ only
+}
+
+// Phase of one channel over the rolling history
+struct ChannelHistory {
+    start: u64,           // Index of the first sample kept since the history was cleared
+    phase: VecDeque<f64>, // Phase (rad)
+    calibrated: bool,     // Phase came from a loop calibration (or the firmware phase tap)
+}
+
+pub struct Timestream {
+    pub tap: ChannelTap,
+    pub channels: String,    // Comma separated channel list
+    pub length: String,      // Samples per capture
+    pub sample_rate: String, // Channel sample rate (Hz) for the time axis
+    pub history: String,     // Seconds of history kept per channel
+    pub live: bool,          // Capture again as soon as the last capture returns
+    pub in_flight: bool,     // A capture has been sent and not yet answered
+    pub message: Option<String>,
+    pub latest: Vec<ChannelSamples>, // Each channel of the last capture
+    sent: Vec<usize>, // Channels of the capture in flight
+    data: BTreeMap<usize, ChannelHistory>,
+}
+
+impl Default for Timestream {
+    fn default() -> Self {
+        Self {
+            tap: ChannelTap::DdcIQ,
+            channels: "0".to_string(),
+            length: "4096".to_string(),
+            sample_rate: "1000000".to_string(),
+            history: "1".to_string(),
+            live: false,
+            in_flight: false,
+            message: None,
+            latest: Vec::new(),
+            sent: Vec::new(),
+            data: BTreeMap::new(),
+        }
+    }
+}
+
+// Parse a comma separated channel list
+fn parse_channels(text: &str) -> Result<Vec<usize>, String> {
+    let channels: Vec<usize> = text
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>().map_err(|_| format!("Invalid channel: {}", s)))
+        .collect::<Result<_, _>>()?;
+    if channels.is_empty() {
+        return Err("Select at least one channel.".to_string());
+    }
+    Ok(channels)
+}
+
+impl Timestream {
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate.trim().parse::<f64>().ok().filter(|r| *r > 0.0).unwrap_or(1.0)
+    }
+
+    fn max_samples(&self) -> usize {
+        (self.history.trim().parse::<f64>().unwrap_or(1.0).max(0.0) * self.sample_rate()) as usize
+    }
+
+    // Selected channels and samples per capture
+    pub fn settings(&self) -> Result<(Vec<usize>, u64), String> {
+        let channels = parse_channels(&self.channels)?;
+        let length = self
+            .length
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|l| *l > 0)
+            .ok_or_else(|| "Invalid capture length.".to_string())?;
+        Ok((channels, length))
+    }
+
+    // Build the capture command for the selected channels
+    pub fn command(&mut self) -> Result<RPCCommand, String> {
+        let (channels, length) = self.settings()?;
+        self.in_flight = true;
+        self.sent = channels.clone();
+        Ok(RPCCommand::CaptureChannels(self.tap, channels, length))
+    }
+
+    // Append a returned capture. cals holds the loop calibration (if any) of the resonator on each channel of
+    // the loaded comb, which turns DDC IQ into phase. The phase tap is scaled from i16 to +-pi.
+    pub fn receive(&mut self, snap: Option<Snap>, cals: &[Option<LoopCal>]) {
+        self.in_flight = false;
+        self.latest.clear();
+        let channels = std::mem::take(&mut self.sent);
+        let phases: Vec<(Vec<f64>, Option<Vec<f64>>, bool)> = match snap {
+            Some(Snap::DdcIQ(iq)) => iq
+                .iter()
+                .zip(&channels)
+                .map(|(samples, &channel)| {
+                    let samples: Vec<Complex<f64>> = samples.iter().map(|z| Complex::new(z.re as f64, z.im as f64)).collect();
+                    match cals.get(channel).copied().flatten() {
+                        Some(cal) => {
+                            let (phase, dissipation) = cal.apply(&samples);
+                            (phase, Some(dissipation), true)
+                        }
+                        None => (samples.iter().map(|z| z.arg()).collect(), None, false),
+                    }
+                })
+                .collect(),
+            Some(Snap::Phase(phase)) => phase
+                .iter()
+                .map(|samples| (samples.iter().map(|p| *p as f64 * PI / 32768.0).collect(), None, true))
+                .collect(),
+            Some(Snap::Raw(_)) => {
+                self.message = Some("Unexpected raw IQ capture.".to_string());
+                return;
+            }
+            None => {
+                self.message = Some("Timestream capture failed.".to_string());
+                self.live = false;
+                return;
+            }
+        };
+        self.message = None;
+
+        let max_samples = self.max_samples();
+        for (&channel, (phase, dissipation, calibrated)) in channels.iter().zip(phases) {
+            let history = self.data.entry(channel).or_insert(ChannelHistory {
+                start: 0,
+                phase: VecDeque::new(),
+                calibrated,
+            });
+            history.calibrated = calibrated;
+            history.phase.extend(phase.iter().copied());
+            let excess = history.phase.len().saturating_sub(max_samples);
+            history.phase.drain(..excess);
+            history.start += excess as u64;
+            self.latest.push(ChannelSamples {
+                channel,
+                phase,
+                dissipation,
+            });
+        }
+    }
+
+    // Draw the timestream pane; returns true when a capture was requested
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut capture = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Tap:");
+            ui.radio_value(&mut self.tap, ChannelTap::DdcIQ, "DDC IQ (loop calibrated)");
+            ui.radio_value(&mut self.tap, ChannelTap::Phase, "Firmware Phase");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Channels:");
+            ui.text_edit_singleline(&mut self.channels);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Samples per Capture:");
+            ui.text_edit_singleline(&mut self.length);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Channel Sample Rate (Hz):");
+            ui.text_edit_singleline(&mut self.sample_rate);
+        });
+        ui.horizontal(|ui| {
+            ui.label("History (s):");
+            ui.text_edit_singleline(&mut self.history);
+        });
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.in_flight, egui::Button::new("Capture")).clicked() {
+                capture = true;
+            }
+            ui.checkbox(&mut self.live, "Live");
+            if ui.button("Clear").clicked() {
+                self.data.clear();
+            }
+        });
+
+        if let Some(ref message) = self.message {
+            ui.label(message);
+        }
+
+        // Phase vs time of the selected channels
+        let selected = parse_channels(&self.channels).unwrap_or_default();
+        let rate = self.sample_rate();
+        Plot::new("timestream_plot")
+            .legend(Legend::default())
+            .x_axis_label("Time (s)")
+            .y_axis_label("Phase (rad)")
+            .show(ui, |plot_ui| {
+                for channel in selected {
+                    let Some(history) = self.data.get(&channel) else {
+                        continue;
+                    };
+                    let step = history.phase.len().div_ceil(MAX_PLOT_POINTS).max(1);
+                    let points: Vec<[f64; 2]> = history
+                        .phase
+                        .iter()
+                        .enumerate()
+                        .step_by(step)
+                        .map(|(i, p)| [(history.start + i as u64) as f64 / rate, *p])
+                        .collect();
+                    let name = if history.calibrated {
+                        format!("Channel {}", channel)
+                    } else {
+                        format!("Channel {} (uncalibrated)", channel)
+                    };
+                    plot_ui.line(Line::new(points).name(name));
+                }
+            });
+
+        capture
+    }
+}
@@ -0,0 +1,77 @@
+// Read-back verification
+// Compares what a set command wrote with what the board reports afterwards and logs mismatches
+// Called to in gui, worker and dac_library
+
+use crate::worker::Verification;
+use eframe::egui;
+use num::Complex;
+use std::fmt::Display;
+use std::time::SystemTime;
+
+// Samples listed individually in a DAC table diff
+const LISTED_SAMPLES: usize = 5;
+
+// Difference between the value a set command returned (after any clamping or rounding by the board) and the
+// value read back; the requested value is only shown as context
+pub fn value_diff<T: PartialEq + Display>(requested: T, returned: T, read: T) -> Option<String> {
+    (returned != read).then(|| format!("set returned {}, read back {} (requested {})", returned, read, requested))
+}
+
+// Differing samples of a written and read back DAC table
+pub fn table_diff(written: &[Complex<i16>], read: &[Complex<i16>]) -> Option<String> {
+    if written.len() != read.len() {
+        return Some(format!("wrote {} samples, read back {}", written.len(), read.len()));
+    }
+    let differing: Vec<usize> = (0..written.len()).filter(|i| written[*i] != read[*i]).collect();
+    if differing.is_empty() {
+        return None;
+    }
+    let listed: Vec<String> = differing
+        .iter()
+        .take(LISTED_SAMPLES)
+        .map(|i| format!("[{}] wrote {}{:+}j, read {}{:+}j", i, written[*i].re, written[*i].im, read[*i].re, read[*i].im))
+        .collect();
+    Some(format!("{} of {} samples differ: {}", differing.len(), written.len(), listed.join("; ")))
+}
+
+#[derive(Default)]
+pub struct VerifyLog {
+    pub enabled: bool,
+    pub checks: u64,                           // Read-backs done
+    pub mismatches: Vec<(SystemTime, String)>, // Oldest first
+}
+
+impl VerifyLog {
+    // Log a read-back; returns the error message for a mismatch
+    pub fn receive(&mut self, verification: Verification) -> Option<String> {
+        self.checks += 1;
+        let diff = verification.diff?;
+        let message = format!("{} read-back mismatch: {}", verification.setting, diff);
+        self.mismatches.push((SystemTime::now(), message.clone()));
+        Some(message)
+    }
+
+    // Draw the verification group; returns the new verify setting when toggled
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<bool> {
+        let mut toggled = None;
+
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.enabled, "Read back every set command").changed() {
+                toggled = Some(self.enabled);
+            }
+            if ui.button("Clear").clicked() {
+                self.mismatches.clear();
+                self.checks = 0;
+            }
+        });
+        ui.label(format!("{} read-backs, {} mismatches", self.checks, self.mismatches.len()));
+        egui::ScrollArea::vertical().id_salt("verify_mismatches").max_height(150.0).show(ui, |ui| {
+            for (time, mismatch) in self.mismatches.iter().rev() {
+                let age = time.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+                ui.colored_label(egui::Color32::RED, format!("{} s ago: {}", age, mismatch));
+            }
+        });
+
+        toggled
+    }
+}
@@ -0,0 +1,148 @@
+// Waterfall spectrogram
+// Stacks the spectra of successive raw IQ captures into a scrolling heatmap to spot interference and tone instability
+// Called to in gui
+
+use crate::live_capture::{spectrum_db, spectrum_freqs};
+use crate::power_map::COLORMAPS;
+use eframe::egui;
+use egui_plot::{Plot, PlotImage, PlotPoint};
+use num::Complex;
+use std::collections::VecDeque;
+
+// Spectra are max-pooled down to at most this many columns
+const MAX_COLUMNS: usize = 2048;
+
+pub struct Waterfall {
+    pub colormap: usize,      // Index into COLORMAPS
+    pub auto_top: bool,       // Follow the strongest bin in the history
+    pub top_db: f64,          // Power mapped to the top of the colormap (dB)
+    pub range_db: f64,        // Dynamic range below top_db (dB)
+    pub history: usize,       // Spectra kept
+    pub paused: bool,
+    rows: VecDeque<Vec<f32>>, // Newest spectrum first
+    span: (f64, f64),         // Frequency offset range (MHz)
+    texture: Option<egui::TextureHandle>,
+    dirty: bool,
+}
+
+impl Default for Waterfall {
+    fn default() -> Self {
+        Self {
+            colormap: 0,
+            auto_top: true,
+            top_db: 0.0,
+            range_db: 80.0,
+            history: 200,
+            paused: false,
+            rows: VecDeque::new(),
+            span: (0.0, 1.0),
+            texture: None,
+            dirty: true,
+        }
+    }
+}
+
+impl Waterfall {
+    // Add the spectrum of one capture
+    pub fn push(&mut self, data: &[Complex<i16>]) {
+        if self.paused || data.is_empty() {
+            return;
+        }
+        let spectrum = spectrum_db(data);
+        let freqs = spectrum_freqs(spectrum.len());
+        let span = (freqs[0], freqs[freqs.len() - 1]);
+        if span != self.span {
+            // Capture length changed, the old rows no longer line up
+            self.rows.clear();
+            self.span = span;
+        }
+
+        let pool = spectrum.len().div_ceil(MAX_COLUMNS);
+        let row: Vec<f32> = spectrum.chunks(pool).map(|c| c.iter().copied().fold(f64::NEG_INFINITY, f64::max) as f32).collect();
+        self.rows.push_front(row);
+        self.rows.truncate(self.history.max(1));
+        self.dirty = true;
+    }
+
+    // Newest spectrum at the top
+    fn build_image(&mut self) -> egui::ColorImage {
+        let width = self.rows.iter().map(|r| r.len()).max().unwrap_or(1).max(1);
+        let height = self.rows.len().max(1);
+        if self.auto_top {
+            self.top_db = self.rows.iter().flatten().fold(f32::NEG_INFINITY, |a, b| a.max(*b)) as f64;
+        }
+        let (top, range) = (self.top_db as f32, self.range_db.max(1.0) as f32);
+        let gradient = COLORMAPS[self.colormap].1;
+
+        let mut pixels = vec![egui::Color32::BLACK; width * height];
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, p) in row.iter().enumerate() {
+                let t = ((p - (top - range)) / range).clamp(0.0, 1.0);
+                let c = gradient.eval_continuous(t as f64);
+                pixels[y * width + x] = egui::Color32::from_rgb(c.r, c.g, c.b);
+            }
+        }
+        egui::ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
+
+    // Draw the waterfall pane
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.label("Spectra of every raw IQ capture (one-shot or live) are added to the waterfall.");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Colormap")
+                .selected_text(COLORMAPS[self.colormap].0)
+                .show_ui(ui, |ui| {
+                    for (i, (name, _)) in COLORMAPS.iter().enumerate() {
+                        self.dirty |= ui.selectable_value(&mut self.colormap, i, *name).changed();
+                    }
+                });
+            ui.label("History (captures):");
+            if ui.add(egui::DragValue::new(&mut self.history).range(1..=5000)).changed() {
+                self.rows.truncate(self.history.max(1));
+                self.dirty = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            self.dirty |= ui.checkbox(&mut self.auto_top, "Auto top").changed();
+            ui.label("Top (dB):");
+            self.dirty |= ui.add_enabled(!self.auto_top, egui::DragValue::new(&mut self.top_db).speed(0.5)).changed();
+            ui.label("Dynamic Range (dB):");
+            self.dirty |= ui.add(egui::DragValue::new(&mut self.range_db).speed(0.5).range(1.0..=200.0)).changed();
+            ui.checkbox(&mut self.paused, "Pause");
+            if ui.button("Clear").clicked() {
+                self.rows.clear();
+                self.dirty = true;
+            }
+        });
+
+        if self.rows.is_empty() {
+            ui.label("No captures yet. Start a live capture or perform a capture.");
+            return;
+        }
+
+        if self.dirty || self.texture.is_none() {
+            let image = self.build_image();
+            self.texture = Some(ui.ctx().load_texture("waterfall", image, egui::TextureOptions::NEAREST));
+            self.dirty = false;
+        }
+        let texture = self.texture.as_ref().unwrap().id();
+        let (f_lo, f_hi) = self.span;
+        let rows = self.rows.len() as f64;
+
+        // Y axis counts captures back from the newest (0 at the top)
+        Plot::new("waterfall_plot")
+            .x_axis_label("Frequency Offset (MHz)")
+            .y_axis_label("Captures Ago")
+            .y_axis_formatter(|mark, _range| format!("{:.0}", -mark.value))
+            .show(ui, |plot_ui| {
+                plot_ui.image(PlotImage::new(
+                    texture,
+                    PlotPoint::new((f_lo + f_hi) / 2.0, -(rows - 1.0) / 2.0),
+                    egui::vec2((f_hi - f_lo).max(f64::EPSILON) as f32, rows as f32),
+                ));
+            });
+    }
+}
@@ -1,40 +1,193 @@
+use crate::dac_comb::{self, table_hash, DacTable};
+use crate::verify;
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
 use futures::AsyncReadExt;
-use gen3_rpc::{client::ExclusiveDroppableReference, Attens, DSPScaleError, Hertz};
+use gen3_rpc::{client::ExclusiveDroppableReference, Attens, DSPScaleError, Hertz, Snap};
 use num::Complex;
 use std::{
     net::{Ipv4Addr, SocketAddrV4},
-    sync::mpsc::{Receiver, Sender},
-    time::SystemTime,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::runtime::Runtime;
 use gen3_rpc::utils::client::SweepConfig;
 use gen3_rpc::client::{CaptureTap, Tap};
 use gen3_rpc::utils::client::Sweep;
 
+// Timed board state reads per DAC table read; the 2 MB table is polled less often than the other settings
+pub const TABLE_POLL_EVERY: u64 = 6;
+
 // Define RPC commands for setting and getting the FFT scale, DAC table, and IF board
 pub enum RPCCommand {
     SetFFTScale(u16),
     GetFFTScale,
     GetDACTable,
-    SetDACTable(Box<[Complex<i16>; 524288]>),
+    SetDACTable(DacTable), // Skipped if the board already holds an identical table
     GetIFFreq,
     SetIFFreq(Hertz),
     GetIFAttens,
     SetIFAttens(Attens),
     SweepConfig(SweepConfig),
     PerformCapture, // New command to perform a capture
+    CaptureChannels(ChannelTap, Vec<usize>, u64), // Capture selected channels of a channelized tap
+    StartLiveCapture(Duration, u64), // Repeat raw IQ captures of the given length at this interval
+    StopLiveCapture,
+    FFTScaleScan(Vec<u16>, Vec<usize>, u64), // DDC IQ capture of these channels at each FFT scale, then restore the scale
+    ReadBoardState,                 // Read every setting back from the board now
+    SetStatePoll(Option<Duration>), // Re-read the board state at this interval (None stops polling)
+    SetVerify(bool),                // Read back every set command and report mismatches
+}
+
+// Channelized capture taps
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChannelTap {
+    DdcIQ,
+    Phase,
 }
 
 // Define RPC responses for connection status, FFT scale, DAC table, and IF board
 pub enum RPCResponse {
     Connected(SystemTime), // Include timestamp in the Connected response
     FFTScale(Option<u16>),
-    DACTable(Option<Box<[Complex<i16>; 524288]>>),
+    FFTScaleClamped(u16, u16), // Requested scale and the value the board clamped it to
+    DACTable(Option<DacTable>),
     IFFreq(Option<Hertz>),
     IFAttens(Option<Attens>),
-    Sweep(Sweep),
+    Sweep(Option<Sweep>),
     CaptureResult(Vec<Complex<i16>>), // New response to send capture results
+    ChannelCapture(Option<Snap>),
+    LiveFrame(LiveFrame),
+    FFTScaleScan(Option<Vec<ScaleCapture>>),
+    BoardState(BoardState),
+    Verified(Verification),
+    Applied(AppliedWrite),
+    WriteFailed(BoardWrite), // A set command the board did not take, with the requested value
+}
+
+// A setting written to the board, in a form that can be written again
+#[derive(Clone)]
+pub enum BoardWrite {
+    FFTScale(u16),
+    DACTable(DacTable, u64), // Table and its table_hash
+    IFFreq(Hertz),
+    IFAttens(f32, f32),      // Input, output (dB)
+}
+
+impl BoardWrite {
+    // Command that writes this value
+    pub fn command(&self) -> RPCCommand {
+        match self {
+            BoardWrite::FFTScale(s) => RPCCommand::SetFFTScale(*s),
+            BoardWrite::DACTable(t, _) => RPCCommand::SetDACTable(t.clone()),
+            BoardWrite::IFFreq(f) => RPCCommand::SetIFFreq(*f),
+            BoardWrite::IFAttens(input, output) => RPCCommand::SetIFAttens(Attens { input: *input, output: *output }),
+        }
+    }
+
+    // Same setting and value
+    pub fn same(&self, other: &BoardWrite) -> bool {
+        match (self, other) {
+            (BoardWrite::FFTScale(a), BoardWrite::FFTScale(b)) => a == b,
+            (BoardWrite::DACTable(_, a), BoardWrite::DACTable(_, b)) => a == b,
+            (BoardWrite::IFFreq(a), BoardWrite::IFFreq(b)) => a == b,
+            (BoardWrite::IFAttens(a, b), BoardWrite::IFAttens(c, d)) => a == c && b == d,
+            _ => false,
+        }
+    }
+}
+
+// A successful set command with the value read from the board just before it
+pub struct AppliedWrite {
+    pub time: SystemTime,
+    pub before: Option<BoardWrite>, // None if the read before the write failed
+    pub after: BoardWrite,
+}
+
+// Outcome of reading back a set command
+pub struct Verification {
+    pub setting: String,
+    pub diff: Option<String>, // None if the board reads back what the set command returned
+}
+
+// Settings read back from the board (None where the read failed)
+pub struct BoardState {
+    pub time: SystemTime,
+    pub fft_scale: Option<u16>,
+    pub if_freq: Option<Hertz>,
+    pub if_attens: Option<Attens>,
+    pub dac_table: Option<DacTable>, // Also None on the timed reads that skip the table
+    pub divergences: Vec<String>, // Settings that changed since they were last set or read by this GUI
+}
+
+// Values last written or read by the worker, to spot changes made by other clients
+#[derive(Default)]
+struct Expected {
+    fft_scale: Option<u16>,
+    if_freq: Option<Hertz>,
+    if_attens: Option<(f32, f32)>,
+    dac_table: Option<u64>, // table_hash of the table
+}
+
+impl Expected {
+    // Describe every setting that differs from what was expected, then expect what was read
+    fn check(&mut self, state: &BoardState) -> Vec<String> {
+        let mut divergences = Vec::new();
+        if let Some(read) = state.fft_scale {
+            if let Some(expected) = self.fft_scale.filter(|e| *e != read) {
+                divergences.push(format!("FFT scale is {}, expected {}", read, expected));
+            }
+            self.fft_scale = Some(read);
+        }
+        if let Some(read) = state.if_freq {
+            if let Some(expected) = self.if_freq.filter(|e| *e != read) {
+                divergences.push(format!("IF frequency is {} Hz, expected {} Hz", read, expected));
+            }
+            self.if_freq = Some(read);
+        }
+        if let Some(ref attens) = state.if_attens {
+            let read = (attens.input, attens.output);
+            if let Some(expected) = self.if_attens.filter(|e| *e != read) {
+                divergences.push(format!(
+                    "IF attenuation is {}/{} dB, expected {}/{} dB (input/output)",
+                    read.0, read.1, expected.0, expected.1
+                ));
+            }
+            self.if_attens = Some(read);
+        }
+        if let Some(ref table) = state.dac_table {
+            let read = table_hash(&table[..]);
+            if self.dac_table.is_some_and(|e| e != read) {
+                divergences.push("DAC table differs from the one last loaded".to_string());
+            }
+            self.dac_table = Some(read);
+        }
+        divergences
+    }
+}
+
+// Capture taken during an FFT scale scan
+pub struct ScaleCapture {
+    pub requested: u16,
+    pub applied: Option<u16>,    // Scale the board accepted (differs from requested if clamped)
+    pub data: Vec<Complex<i16>>, // DDC IQ of every captured channel (empty if the capture failed)
+}
+
+// One capture of the live capture mode
+pub struct LiveFrame {
+    pub sequence: u64,           // Captures attempted since live mode started
+    pub missed: u64,             // Capture slots missed because capturing was slower than the interval
+    pub failed: u64,             // Captures that returned an error
+    pub data: Vec<Complex<i16>>, // Raw IQ (empty if this capture failed)
+}
+
+// Live capture schedule kept by the worker
+struct LiveCapture {
+    interval: Duration,
+    length: u64,
+    next: Instant,
+    sequence: u64,
+    missed: u64,
+    failed: u64,
 }
 
 pub fn worker_thread(
@@ -85,19 +238,157 @@ pub fn worker_thread(
                 let capture = board.get_capture().await?;
 
                 let mut operation_in_progress = false;
+                let mut live: Option<LiveCapture> = None;
+                let mut expected = Expected::default();
+                let mut verify = false;
+
+                // Last table read from the board with its hash, answering GetDACTable without another 2 MB transfer.
+                // Filled by GetDACTable and board state reads; cleared by every table write, a failed read and reconnecting.
+                // Uploads are never skipped on the cache alone, SetDACTable checks the board first.
+                let mut cached_table: Option<(u64, DacTable)> = None;
+
+                // Read the board state right away, then every state_interval. Timed reads include the DAC table on
+                // every TABLE_POLL_EVERY-th poll (starting with the first); ReadBoardState always includes it.
+                let mut state_interval = Some(Duration::from_secs(10));
+                let mut next_state = Some(Instant::now());
+                let mut polls: u64 = 0;
 
                 loop {
-                    match command.recv().unwrap() {
+                    // Wait for commands only until the next live capture or state read is due
+                    let deadline = live.as_ref().map(|l| l.next).into_iter().chain(next_state).min();
+                    let next = match deadline {
+                        Some(deadline) => match command.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                            Ok(c) => Some(c),
+                            Err(RecvTimeoutError::Timeout) => None,
+                            Err(RecvTimeoutError::Disconnected) => panic!("GUI disconnected"),
+                        },
+                        None => Some(command.recv().unwrap()),
+                    };
+
+                    // Read all board state and report it with any divergence
+                    let read_state = match next {
+                        Some(RPCCommand::ReadBoardState) => true,
+                        None => next_state.is_some_and(|t| t <= Instant::now()),
+                        Some(_) => false,
+                    };
+                    if read_state {
+                        let with_table = match next {
+                            Some(_) => true,
+                            None => {
+                                polls += 1;
+                                (polls - 1) % TABLE_POLL_EVERY == 0
+                            }
+                        };
+                        let mut state = BoardState {
+                            time: SystemTime::now(),
+                            fft_scale: dsp_scale.get_fft_scale().await.ok(),
+                            if_freq: if_board.get_freq().await.ok(),
+                            if_attens: if_board.get_attens().await.ok(),
+                            dac_table: if with_table { dac_table.get_dac_table().await.ok().map(DacTable::from) } else { None },
+                            divergences: Vec::new(),
+                        };
+                        state.divergences = expected.check(&state);
+                        if let Some(ref table) = state.dac_table {
+                            cached_table = Some((table_hash(&table[..]), table.clone()));
+                        }
+                        response.send(RPCResponse::BoardState(state)).unwrap();
+                        next_state = state_interval.map(|i| Instant::now() + i);
+                        continue;
+                    }
+
+                    let Some(next) = next else {
+                        let Some(l) = live.as_mut().filter(|l| l.next <= Instant::now()) else {
+                            continue;
+                        };
+                        let rfchain = gen3_rpc::client::RFChain {
+                            dac_table: &dac_table,
+                            if_board: &if_board,
+                            dsp_scale: &dsp_scale,
+                        };
+                        let data = match capture.capture(CaptureTap::new(&rfchain, Tap::RawIQ), l.length).await {
+                            Ok(Snap::Raw(data)) => data,
+                            _ => {
+                                l.failed += 1;
+                                Vec::new()
+                            }
+                        };
+                        l.sequence += 1;
+
+                        // Skip the slots that passed while capturing instead of bursting to catch up
+                        l.next += l.interval;
+                        let now = Instant::now();
+                        if l.next < now {
+                            let behind = (now - l.next).as_secs_f64() / l.interval.as_secs_f64();
+                            l.missed += behind.ceil() as u64;
+                            l.next += l.interval.mul_f64(behind.ceil());
+                        }
+
+                        response
+                            .send(RPCResponse::LiveFrame(LiveFrame {
+                                sequence: l.sequence,
+                                missed: l.missed,
+                                failed: l.failed,
+                                data,
+                            }))
+                            .unwrap();
+                        continue;
+                    };
+
+                    match next {
+                        // Handle the live capture commands
+                        RPCCommand::StartLiveCapture(interval, length) => {
+                            live = Some(LiveCapture {
+                                interval: interval.max(Duration::from_millis(1)),
+                                length,
+                                next: Instant::now(),
+                                sequence: 0,
+                                missed: 0,
+                                failed: 0,
+                            });
+                        }
+                        RPCCommand::StopLiveCapture => {
+                            live = None;
+                        }
+                        // Handle the board state commands
+                        RPCCommand::ReadBoardState => {}
+                        RPCCommand::SetStatePoll(interval) => {
+                            state_interval = interval;
+                            next_state = interval.map(|i| Instant::now() + i);
+                        }
+                        RPCCommand::SetVerify(enabled) => {
+                            verify = enabled;
+                        }
                         // Handle the SetFFTScale command
                         RPCCommand::SetFFTScale(i) => {
                             println!("Received SetFFTScale command with value: {}", i);
+                            let before = dsp_scale.get_fft_scale().await.ok().map(BoardWrite::FFTScale);
                             let r = dsp_scale.set_fft_scale(i).await;
-                            match r {
-                                Ok(i) => response.send(RPCResponse::FFTScale(Some(i))).unwrap(),
-                                Err(DSPScaleError::Clamped(i)) => {
-                                    response.send(RPCResponse::FFTScale(Some(i))).unwrap()
+                            let applied = match r {
+                                Ok(i) => {
+                                    response.send(RPCResponse::FFTScale(Some(i))).unwrap();
+                                    Some(i)
+                                }
+                                Err(DSPScaleError::Clamped(clamped)) => {
+                                    response.send(RPCResponse::FFTScaleClamped(i, clamped)).unwrap();
+                                    Some(clamped)
                                 }
-                                Err(_) => response.send(RPCResponse::FFTScale(None)).unwrap(),
+                                Err(_) => {
+                                    response.send(RPCResponse::FFTScale(None)).unwrap();
+                                    response.send(RPCResponse::WriteFailed(BoardWrite::FFTScale(i))).unwrap();
+                                    None
+                                }
+                            };
+                            expected.fft_scale = applied.or(expected.fft_scale);
+                            if let Some(applied) = applied {
+                                let after = BoardWrite::FFTScale(applied);
+                                response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                            }
+                            if let Some(applied) = applied.filter(|_| verify) {
+                                let diff = match dsp_scale.get_fft_scale().await {
+                                    Ok(read) => verify::value_diff(i, applied, read),
+                                    Err(e) => Some(format!("read-back failed: {:?}", e)),
+                                };
+                                response.send(RPCResponse::Verified(Verification { setting: "FFT scale".to_string(), diff })).unwrap();
                             }
                         }
                         // Handle the GetFFTScale command
@@ -107,10 +398,19 @@ pub fn worker_thread(
                         }
                         // Handle the GetDACTable command
                         RPCCommand::GetDACTable => {
+                            if let Some((_, ref table)) = cached_table {
+                                response.send(RPCResponse::DACTable(Some(table.clone()))).unwrap();
+                                continue;
+                            }
                             let r = dac_table.get_dac_table().await;
                             match r {
-                                Ok(d) => response.send(RPCResponse::DACTable(Some(d))).unwrap(),
+                                Ok(d) => {
+                                    let d = DacTable::from(d);
+                                    cached_table = Some((table_hash(&d[..]), d.clone()));
+                                    response.send(RPCResponse::DACTable(Some(d))).unwrap()
+                                }
                                 Err(e) => {
+                                    cached_table = None;
                                     eprintln!("Failed to get DAC table: {}", e);
                                     response.send(RPCResponse::DACTable(None)).unwrap()
                                 },
@@ -118,13 +418,49 @@ pub fn worker_thread(
                         }
                         // Handle the SetDACTable command
                         RPCCommand::SetDACTable(data) => {
-                            let data_clone = data.clone();
-                            let r = dac_table.set_dac_table(data).await;
+                            let hash = table_hash(&data[..]);
+                            // Check what the board holds now: the upload is only skipped if it already has this table,
+                            // and the read is the table undo restores
+                            let current = dac_table.get_dac_table().await.ok().map(|t| {
+                                let t = DacTable::from(t);
+                                (table_hash(&t[..]), t)
+                            });
+                            cached_table = None;
+                            let before = current.as_ref().map(|(h, t)| BoardWrite::DACTable(t.clone(), *h));
+                            let r = if current.as_ref().is_some_and(|(h, _)| *h == hash) {
+                                println!("DAC table unchanged, upload skipped");
+                                Ok(())
+                            } else {
+                                dac_table.set_dac_table(dac_comb::to_board(&data)).await
+                            };
                             match r {
-                                Ok(_) => response.send(RPCResponse::DACTable(Some(data_clone))).unwrap(),
+                                Ok(_) if verify => {
+                                    // Report what is on the board rather than what was sent
+                                    let (table, diff) = match dac_table.get_dac_table().await {
+                                        Ok(read) => {
+                                            let diff = verify::table_diff(&data[..], &read[..]);
+                                            (DacTable::from(read), diff)
+                                        }
+                                        Err(e) => (data, Some(format!("read-back failed: {:?}", e))),
+                                    };
+                                    let hash = table_hash(&table[..]);
+                                    expected.dac_table = Some(hash);
+                                    let after = BoardWrite::DACTable(table.clone(), hash);
+                                    response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                                    response.send(RPCResponse::DACTable(Some(table))).unwrap();
+                                    response.send(RPCResponse::Verified(Verification { setting: "DAC table".to_string(), diff })).unwrap();
+                                }
+                                Ok(_) => {
+                                    expected.dac_table = Some(hash);
+                                    let after = BoardWrite::DACTable(data.clone(), hash);
+                                    response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                                    response.send(RPCResponse::DACTable(Some(data))).unwrap()
+                                }
                                 Err(e) => {
+                                    // A failed upload may have left anything on the board
                                     eprintln!("Failed to set DAC table: {}", e);
-                                    response.send(RPCResponse::DACTable(None)).unwrap()
+                                    response.send(RPCResponse::DACTable(None)).unwrap();
+                                    response.send(RPCResponse::WriteFailed(BoardWrite::DACTable(data, hash))).unwrap()
                                 },
                             }
                         }
@@ -135,12 +471,26 @@ pub fn worker_thread(
                         }
                         // Handle the SetIFFreq command
                         RPCCommand::SetIFFreq(freq) => {
+                            let before = if_board.get_freq().await.ok().map(BoardWrite::IFFreq);
                             let r = if_board.set_freq(freq).await;
                             match r {
-                                Ok(f) => response.send(RPCResponse::IFFreq(Some(f))).unwrap(),
+                                Ok(f) => {
+                                    expected.if_freq = Some(f);
+                                    let after = BoardWrite::IFFreq(f);
+                                    response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                                    response.send(RPCResponse::IFFreq(Some(f))).unwrap();
+                                    if verify {
+                                        let diff = match if_board.get_freq().await {
+                                            Ok(read) => verify::value_diff(freq, f, read),
+                                            Err(e) => Some(format!("read-back failed: {:?}", e)),
+                                        };
+                                        response.send(RPCResponse::Verified(Verification { setting: "IF frequency".to_string(), diff })).unwrap();
+                                    }
+                                }
                                 Err(e) => {
                                     eprintln!("Failed to set IF frequency: {:?}", e);
-                                    response.send(RPCResponse::IFFreq(None)).unwrap()
+                                    response.send(RPCResponse::IFFreq(None)).unwrap();
+                                    response.send(RPCResponse::WriteFailed(BoardWrite::IFFreq(freq))).unwrap()
                                 },
                             }
                         }
@@ -158,12 +508,29 @@ pub fn worker_thread(
                         }
                         // Handle the SetIFAttens command
                         RPCCommand::SetIFAttens(attens) => {
+                            let before = if_board.get_attens().await.ok().map(|a| BoardWrite::IFAttens(a.input, a.output));
+                            let (input, output) = (attens.input, attens.output);
+                            let requested = format!("{}/{} dB", input, output);
                             let r = if_board.set_attens(attens).await;
                             match r {
-                                Ok(a) => response.send(RPCResponse::IFAttens(Some(a))).unwrap(),
+                                Ok(a) => {
+                                    expected.if_attens = Some((a.input, a.output));
+                                    let after = BoardWrite::IFAttens(a.input, a.output);
+                                    response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                                    let returned = format!("{}/{} dB", a.input, a.output);
+                                    response.send(RPCResponse::IFAttens(Some(a))).unwrap();
+                                    if verify {
+                                        let diff = match if_board.get_attens().await {
+                                            Ok(read) => verify::value_diff(requested, returned, format!("{}/{} dB", read.input, read.output)),
+                                            Err(e) => Some(format!("read-back failed: {:?}", e)),
+                                        };
+                                        response.send(RPCResponse::Verified(Verification { setting: "IF attenuation (input/output)".to_string(), diff })).unwrap();
+                                    }
+                                }
                                 Err(e) => {
                                     eprintln!("Failed to set IF attenuations: {:?}", e);
-                                    response.send(RPCResponse::IFAttens(None)).unwrap()
+                                    response.send(RPCResponse::IFAttens(None)).unwrap();
+                                    response.send(RPCResponse::WriteFailed(BoardWrite::IFAttens(input, output))).unwrap()
                                 },
                             }
                         }
@@ -213,16 +580,86 @@ pub fn worker_thread(
 
                             operation_in_progress = false;
                         }
+                        // Handle the CaptureChannels command
+                        RPCCommand::CaptureChannels(kind, channels, length) => {
+                            let rfchain = gen3_rpc::client::RFChain {
+                                dac_table: &dac_table,
+                                if_board: &if_board,
+                                dsp_scale: &dsp_scale,
+                            };
+                            let tap = match kind {
+                                ChannelTap::DdcIQ => Tap::DDCIQ(&channels),
+                                ChannelTap::Phase => Tap::Phase(&channels),
+                            };
+
+                            match capture.capture(CaptureTap::new(&rfchain, tap), length).await {
+                                Ok(snap) => response.send(RPCResponse::ChannelCapture(Some(snap))).unwrap(),
+                                Err(e) => {
+                                    eprintln!("Channel capture failed: {:?}", e);
+                                    response.send(RPCResponse::ChannelCapture(None)).unwrap();
+                                }
+                            }
+                        }
+                        // Handle the FFTScaleScan command
+                        RPCCommand::FFTScaleScan(scales, channels, length) => {
+                            println!("Scanning {} FFT scales", scales.len());
+
+                            // Without the current scale the board could not be left as it was
+                            let Ok(original) = dsp_scale.get_fft_scale().await else {
+                                eprintln!("Scale scan skipped: failed to read the FFT scale");
+                                response.send(RPCResponse::FFTScaleScan(None)).unwrap();
+                                continue;
+                            };
+                            let mut captures = Vec::new();
+                            for requested in scales {
+                                let applied = match dsp_scale.set_fft_scale(requested).await {
+                                    Ok(i) | Err(DSPScaleError::Clamped(i)) => Some(i),
+                                    Err(_) => None,
+                                };
+                                let data = match applied {
+                                    Some(_) => {
+                                        let rfchain = gen3_rpc::client::RFChain {
+                                            dac_table: &dac_table,
+                                            if_board: &if_board,
+                                            dsp_scale: &dsp_scale,
+                                        };
+                                        // The scale acts on the FFT, so only channelized data shows its effect
+                                        match capture.capture(CaptureTap::new(&rfchain, Tap::DDCIQ(&channels)), length).await {
+                                            Ok(Snap::DdcIQ(iq)) => iq.into_iter().flatten().collect(),
+                                            _ => Vec::new(),
+                                        }
+                                    }
+                                    None => Vec::new(),
+                                };
+                                captures.push(ScaleCapture { requested, applied, data });
+                            }
+
+                            // Leave the board as it was; the GUI applies the chosen scale
+                            let restored = dsp_scale.set_fft_scale(original).await.ok();
+                            if restored.is_none() {
+                                eprintln!("Failed to restore FFT scale");
+                            }
+                            expected.fft_scale = restored;
+                            response.send(RPCResponse::FFTScaleScan(Some(captures))).unwrap();
+                        }
                         // Handle the SweepConfig command
                         RPCCommand::SweepConfig(config) => {
                             if operation_in_progress {
                                 eprintln!("Sweep skipped: Another operation is in progress."); // Prevent sweep overlap 
+                                response.send(RPCResponse::Sweep(None)).unwrap();
                                 continue;
                             }
 
                             operation_in_progress = true;
                             println!("Performing Sweep:");
 
+                            // Settings the sweep may step, to log what it leaves behind for undo
+                            let before = [
+                                dsp_scale.get_fft_scale().await.ok().map(BoardWrite::FFTScale),
+                                if_board.get_freq().await.ok().map(BoardWrite::IFFreq),
+                                if_board.get_attens().await.ok().map(|a| BoardWrite::IFAttens(a.input, a.output)),
+                            ];
+
                             let result = config
                                 .sweep(
                                     &capture,
@@ -234,13 +671,32 @@ pub fn worker_thread(
                                 )
                                 .await;
 
+                            // The sweep steps the LO, attenuation and scale: expect and log where it left them
+                            let fft_scale = dsp_scale.get_fft_scale().await.ok();
+                            let if_freq = if_board.get_freq().await.ok();
+                            let if_attens = if_board.get_attens().await.ok().map(|a| (a.input, a.output));
+                            expected.fft_scale = fft_scale;
+                            expected.if_freq = if_freq;
+                            expected.if_attens = if_attens;
+                            let after = [
+                                fft_scale.map(BoardWrite::FFTScale),
+                                if_freq.map(BoardWrite::IFFreq),
+                                if_attens.map(|(input, output)| BoardWrite::IFAttens(input, output)),
+                            ];
+                            for (before, after) in before.into_iter().zip(after) {
+                                if let Some(after) = after.filter(|a| !before.as_ref().is_some_and(|b| b.same(a))) {
+                                    response.send(RPCResponse::Applied(AppliedWrite { time: SystemTime::now(), before, after })).unwrap();
+                                }
+                            }
+
                             match result {
                                 Ok(sweep) => {
                                     println!("Sweep successful");
-                                    response.send(RPCResponse::Sweep(sweep)).unwrap();
+                                    response.send(RPCResponse::Sweep(Some(sweep))).unwrap();
                                 }
                                 Err(e) => {
                                     eprintln!("Sweep failed: {:?}", e);
+                                    response.send(RPCResponse::Sweep(None)).unwrap();
                                 }
                             }
 